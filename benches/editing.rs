@@ -0,0 +1,88 @@
+/*! Timings for the core editing operations on a large buffer, run with
+`cargo bench`. Criterion keeps the results of the last run in `target/criterion`
+and reports the change against them, so numbers can be compared before and
+after a change. */
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+use std::time::Instant;
+use stte_rs::buffer::Buffer;
+
+const LINES: usize = 100_000;
+const LONG_LINE: usize = 20_000;
+
+/// A buffer of `LINES` short lines with the cursor halfway through.
+fn large_buffer() -> Buffer {
+    let large: String = (0..LINES)
+        .map(|idx| format!("\tline {} with some text to move through\n", idx))
+        .collect();
+    let mut buffer = Buffer::from(large.as_str());
+    buffer.set_cursor_pos(large.chars().count() / 2);
+    buffer
+}
+
+/// Undoes every edit, so the buffer and its history don't grow from one batch to the next.
+fn undo_all(buffer: &mut Buffer) {
+    buffer.end_undo_step();
+    while buffer.undo().is_some() {}
+}
+
+fn editing(c: &mut Criterion) {
+    let mut buffer = large_buffer();
+    c.bench_function("insert_char", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            for _ in 0..iters {
+                buffer.insert_char(black_box('x'));
+            }
+            let elapsed = start.elapsed();
+            undo_all(&mut buffer);
+            elapsed
+        })
+    });
+    // delete_char clears the terminal below the cursor, which is harmless here
+    c.bench_function("delete_char", |b| {
+        b.iter_custom(|iters| {
+            for _ in 0..iters {
+                buffer.insert_char('x');
+            }
+            buffer.end_undo_step();
+            let start = Instant::now();
+            for _ in 0..iters {
+                buffer.delete_char().unwrap();
+            }
+            let elapsed = start.elapsed();
+            undo_all(&mut buffer);
+            elapsed
+        })
+    });
+}
+
+fn movement(c: &mut Criterion) {
+    let mut buffer = large_buffer();
+    // Starting over at either end keeps every call an actual move
+    c.bench_function("move_cursor_down", |b| {
+        b.iter(|| {
+            if !buffer.move_cursor_down() {
+                buffer.set_cursor_pos(0);
+            }
+        })
+    });
+    c.bench_function("move_cursor_up", |b| {
+        b.iter(|| {
+            if !buffer.move_cursor_up() {
+                buffer.set_cursor_pos(buffer.len_chars());
+            }
+        })
+    });
+
+    let long_line: String = "ab\tcd ".repeat(LONG_LINE / 6) + "\n";
+    let long = Buffer::from(long_line.as_str());
+    let column = long_line.chars().count() - 1;
+    c.bench_function("get_char_column_width (long)", |b| {
+        b.iter(|| black_box(long.get_char_column_width(black_box(column), 0)))
+    });
+}
+
+criterion_group!(benches, editing, movement);
+criterion_main!(benches);
@@ -1,17 +1,21 @@
+use crate::language::CommentSyntax;
+use crate::number::{add_to_number, find_number};
+use crate::path::find_project_root;
 use crossterm::terminal::ClearType;
 use crossterm::{execute, terminal};
 use ropey::iter::{Bytes, Chars, Chunks, Lines};
 use ropey::{Rope, RopeSlice};
 use std::error::Error;
 use std::fmt;
-use std::fs::File;
-use std::io::{self, BufReader, BufWriter, ErrorKind};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use unicode_width::UnicodeWidthChar;
 
-use crate::screen::Screen;
-
 const TAB_WIDTH: usize = 8;
+const UNDO_LEVELS: usize = 1000;
 
 #[derive(Debug)]
 pub struct BufferError {
@@ -52,6 +56,7 @@ pub enum Status {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum LineEnding {
     LF,
     CRLF,
@@ -73,6 +78,84 @@ impl LineEnding {
     }
 }
 
+/// How a level of indentation is written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+impl fmt::Display for IndentStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndentStyle::Tabs => write!(f, "tabs"),
+            IndentStyle::Spaces(width) => write!(f, "spaces:{}", width),
+        }
+    }
+}
+
+/// Whether an edit put text in or took it out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    Insert,
+    Remove,
+}
+
+impl EditKind {
+    /// The kind of edit that takes this one back.
+    fn inverse(self) -> Self {
+        match self {
+            EditKind::Insert => EditKind::Remove,
+            EditKind::Remove => EditKind::Insert,
+        }
+    }
+}
+
+/// One change to the text, kept so it can be undone and redone.
+#[derive(Debug, Clone)]
+struct EditOp {
+    kind: EditKind,
+    /// Char index the text was inserted at or removed from.
+    pos: usize,
+    text: String,
+    /// Where the cursor was before the edit, restored when it's undone.
+    cursor_before: usize,
+    /// Edits in the same group are undone and redone together.
+    group: usize,
+}
+
+/// Whether a copy of the file on disk is kept when saving over it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backup {
+    Off,
+    /// One copy at `file~`, replaced on every save.
+    Simple,
+    /// Copies at `file.~1~`, `file.~2~` and so on, keeping only the newest few.
+    Numbered(usize),
+}
+
+impl Backup {
+    /// Reads `off`, `simple` or `numbered`, keeping `kept` numbered copies.
+    pub fn parse(mode: &str, kept: usize) -> Option<Self> {
+        match mode {
+            "off" => Some(Backup::Off),
+            "simple" => Some(Backup::Simple),
+            "numbered" => Some(Backup::Numbered(kept.max(1))),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Backup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backup::Off => write!(f, "off"),
+            Backup::Simple => write!(f, "simple"),
+            Backup::Numbered(kept) => write!(f, "numbered, keeping {}", kept),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Buffer {
     text: Rope,                 // text from a file or in memory
@@ -80,11 +163,30 @@ pub struct Buffer {
     status: Status, // Whether the buffer has been modified, left unchanged, or is being saved back to disk?
     cursor_pos: usize,
     line_ending: LineEnding,
+    indent_style: Option<IndentStyle>, // Indentation detected from the file, `None` means use the config default
+    folds: Vec<Range<usize>>, // Folded line ranges, sorted. The first line of each stays visible as its header
+    word_chars: WordChars,
+    disk_line_ending: Option<LineEnding>, // Ending to write on save when `line_ending` was normalized to LF on load
+    tab_width: usize,
+    tab_stops: Option<TabStops>, // Overrides the uniform stops every `tab_width` columns when set
+    trim_trailing_whitespace: bool, // Strip spaces and tabs from line ends on save
+    insert_final_newline: bool,  // Make sure the file ends with a line ending on save
+    project_root: PathBuf,
+    disk_modified: Option<SystemTime>, // Modification time of the file when it was last loaded or saved
+    edits: Vec<EditOp>, // Every edit made, oldest first. Those from `undo_index` on have been undone
+    undo_index: usize,
+    edit_group: usize, // Group the next edit joins, unless `group_closed` starts a new one
+    group_closed: bool,
+    typing_at: Option<usize>, // Where a typed character would continue the previous one's undo group
+    saved_index: Option<usize>, // `undo_index` when the text last matched the file, if undo can get back there
+    undo_levels: usize,         // Most undo steps kept. Older ones are dropped
+    selection_anchor: Option<usize>, // Where the selection started. It runs from here to the cursor
 }
 
 impl Buffer {
     pub fn new(path: Option<PathBuf>) -> Buffer {
         let text = Rope::new();
+        let project_root = find_project_root(path.as_deref());
         Buffer {
             text,
             file_path: path,
@@ -95,6 +197,35 @@ impl Buffer {
             } else {
                 LineEnding::LF
             },
+            indent_style: None,
+            folds: Vec::new(),
+            word_chars: WordChars::default(),
+            disk_line_ending: None,
+            tab_width: TAB_WIDTH,
+            tab_stops: None,
+            trim_trailing_whitespace: false,
+            insert_final_newline: false,
+            project_root,
+            disk_modified: None,
+            edits: Vec::new(),
+            undo_index: 0,
+            edit_group: 0,
+            group_closed: true,
+            typing_at: None,
+            saved_index: Some(0),
+            undo_levels: UNDO_LEVELS,
+            selection_anchor: None,
+        }
+    }
+
+    /// A buffer holding `text`, with its line ending and indentation detected from the content.
+    fn with_text(text: Rope, path: Option<PathBuf>) -> Buffer {
+        let buffer = Buffer::new(path);
+        Buffer {
+            line_ending: detect_line_ending(&text).unwrap_or(buffer.line_ending),
+            indent_style: detect_indent_style(&text),
+            text,
+            ..buffer
         }
     }
 
@@ -111,12 +242,10 @@ impl Buffer {
     are different cursors.
     This returns the width for characters so the cursors can be synced*/
     pub fn get_char_column_width(&self, x: usize, y: usize) -> usize {
+        let tab_stops = self.tab_stops();
         let mut visual_width = 0;
-        for (idx, ch) in self.text.line(y).chars().take(x).enumerate() {
-            visual_width += match ch {
-                '\t' => TAB_WIDTH - (visual_width % TAB_WIDTH),
-                _ => ch.width().unwrap_or(1),
-            };
+        for ch in self.text.line(y).chars().take(x) {
+            visual_width += char_width(ch, visual_width, &tab_stops);
         }
         visual_width
     }
@@ -126,76 +255,297 @@ impl Buffer {
         self.get_char_column_width(cursor_x, cursor_y)
     }
 
+    /** Finds the char index within `line` that sits at `target_visual_x`.
+    Targets past the end of the line land before its line ending, never inside it. */
     fn get_char_index_from_visual_x(&self, line: usize, target_visual_x: usize) -> usize {
+        let content_len = self.line_content_end(line) - self.text.line_to_char(line);
+        let tab_stops = self.tab_stops();
         let mut visual_x = 0;
-        for (idx, ch) in self.text.line(line).chars().enumerate() {
-            let char_width = match ch {
-                '\t' => TAB_WIDTH - (visual_x % TAB_WIDTH),
-                _ => ch.width().unwrap_or(1),
-            };
-            if visual_x + char_width > target_visual_x {
+        for (idx, ch) in self.text.line(line).chars().take(content_len).enumerate() {
+            let width = char_width(ch, visual_x, &tab_stops);
+            if visual_x + width > target_visual_x {
                 return idx;
             }
-            visual_x += char_width;
+            visual_x += width;
         }
-        self.text.line(line).len_chars()
+        content_len
     }
 
-    pub fn move_cursor_left(&mut self) {
-        if self.cursor_pos > 0 {
+    /** Moves the cursor one character to the left.
+    Returns `false` if the cursor was already at the start of its line. */
+    pub fn move_cursor_left(&mut self) -> bool {
+        let (cursor_x, _) = self.get_cursor_xy();
+        if cursor_x > 0 {
             self.cursor_pos -= 1;
+            return true;
         }
+        false
     }
 
-    pub fn move_cursor_right(&mut self) {
-        if self.cursor_pos < self.text.len_chars() {
+    /** Moves the cursor one character to the right.
+    Returns `false` if the cursor was already at the end of its line. */
+    pub fn move_cursor_right(&mut self) -> bool {
+        if self.cursor_pos < self.line_content_end(self.cursor_row()) {
             self.cursor_pos += 1;
+            return true;
+        }
+        false
+    }
+
+    /** Moves the cursor to the end of the previous line.
+    Returns `false` if the cursor is on the first line. */
+    pub fn move_to_previous_line_end(&mut self) -> bool {
+        if let Some(row) = self.prev_visible_line(self.cursor_row()) {
+            self.cursor_pos = self.line_content_end(row);
+            return true;
+        }
+        false
+    }
+
+    /** Moves the cursor to the start of the next line.
+    Returns `false` if the cursor is on the last line. */
+    pub fn move_to_next_line_start(&mut self) -> bool {
+        if let Some(row) = self.next_visible_line(self.cursor_row()) {
+            self.cursor_pos = self.text.line_to_char(row);
+            return true;
+        }
+        false
+    }
+
+    /// Moves the cursor to the first character of its line.
+    pub fn move_cursor_line_start(&mut self) {
+        self.cursor_pos = self.text.line_to_char(self.cursor_row());
+    }
+
+    /// Moves the cursor past the last character of its line, in front of the line ending.
+    pub fn move_cursor_line_end(&mut self) {
+        self.cursor_pos = self.line_content_end(self.cursor_row());
+    }
+
+    /// Char index just past the last character of `line`, before its line ending.
+    pub fn line_content_end(&self, line: usize) -> usize {
+        let line_start = self.text.line_to_char(line);
+        let slice = self.text.line(line);
+        let mut len = slice.len_chars();
+        if len > 0 && is_line_break(slice.char(len - 1)) {
+            len -= 1;
+            // \r\n is a single line ending
+            if len > 0 && slice.char(len) == '\n' && slice.char(len - 1) == '\r' {
+                len -= 1;
+            }
         }
+        line_start + len
     }
 
-    pub fn move_cursor_up(&mut self) {
+    /** Moves the cursor to the same visual column on the previous line.
+    Returns `false` if the cursor was already on the first line. */
+    pub fn move_cursor_up(&mut self) -> bool {
         let (cursor_x, cursor_y) = self.get_cursor_xy();
-        if cursor_y > 0 {
-            let target_y = cursor_y - 1;
-            let target_line = self.text.line(target_y);
+        if let Some(target_y) = self.prev_visible_line(cursor_y) {
             let visual_x = self.get_char_column_width(cursor_x, cursor_y);
             let new_x = self.get_char_index_from_visual_x(target_y, visual_x);
             self.cursor_pos = self.text.line_to_char(target_y) + new_x;
+            return true;
         }
+        false
     }
 
-    pub fn move_cursor_down(&mut self) {
+    /** Moves the cursor to the same visual column on the next line.
+    Returns `false` if the cursor was already on the last line. */
+    pub fn move_cursor_down(&mut self) -> bool {
         let (cursor_x, cursor_y) = self.get_cursor_xy();
-        if cursor_y < self.text.len_lines() - 1 {
-            let target_y = cursor_y + 1;
-            let target_line = self.text.line(target_y);
+        if let Some(target_y) = self.next_visible_line(cursor_y) {
             let visual_x = self.get_char_column_width(cursor_x, cursor_y);
             let new_x = self.get_char_index_from_visual_x(target_y, visual_x);
             self.cursor_pos = self.text.line_to_char(target_y) + new_x;
+            return true;
+        }
+        false
+    }
+
+    /** Moves the cursor left past any whitespace and then the word before it, stopping
+    at the start of the line. A run of punctuation counts as a word of its own. Returns
+    `false` if the cursor was already at the start of its line. */
+    pub fn move_cursor_word_left(&mut self) -> bool {
+        let line_start = self.text.line_to_char(self.cursor_row());
+        let start = self.word_start_before(line_start);
+        let moved = start < self.cursor_pos;
+        self.cursor_pos = start;
+        moved
+    }
+
+    /** Moves the cursor right past any whitespace and then the word after it, stopping
+    at the end of the line. A run of punctuation counts as a word of its own. Returns
+    `false` if the cursor was already at the end of its line. */
+    pub fn move_cursor_word_right(&mut self) -> bool {
+        let line_end = self.line_content_end(self.cursor_row());
+        let mut pos = self.cursor_pos;
+        while pos < line_end && self.char_class(self.text.char(pos)) == CharClass::Space {
+            pos += 1;
+        }
+        if pos < line_end {
+            let class = self.char_class(self.text.char(pos));
+            while pos < line_end && self.char_class(self.text.char(pos)) == class {
+                pos += 1;
+            }
+        }
+        let moved = pos > self.cursor_pos;
+        self.cursor_pos = pos;
+        moved
+    }
+
+    /** Where the word before the cursor starts, after skipping back over any whitespace,
+    going no further back than `limit`. */
+    fn word_start_before(&self, limit: usize) -> usize {
+        let mut pos = self.cursor_pos;
+        while pos > limit && self.char_class(self.text.char(pos - 1)) == CharClass::Space {
+            pos -= 1;
+        }
+        if pos > limit {
+            let class = self.char_class(self.text.char(pos - 1));
+            while pos > limit && self.char_class(self.text.char(pos - 1)) == class {
+                pos -= 1;
+            }
+        }
+        pos
+    }
+
+    fn char_class(&self, ch: char) -> CharClass {
+        if ch.is_whitespace() {
+            CharClass::Space
+        } else if self.word_chars.contains(ch) {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+
+    /** Moves the cursor up by `rows` visible lines, or to the first line if there
+    aren't that many, keeping its visual column. Returns `false` if it was already on the
+    first line. */
+    pub fn move_cursor_page_up(&mut self, rows: usize) -> bool {
+        let (cursor_x, cursor_y) = self.get_cursor_xy();
+        let mut target_y = cursor_y;
+        for _ in 0..rows {
+            match self.prev_visible_line(target_y) {
+                Some(line) => target_y = line,
+                None => break,
+            }
+        }
+        self.move_cursor_to_row(cursor_x, cursor_y, target_y)
+    }
+
+    /** Moves the cursor down by `rows` visible lines, or to the last line if there
+    aren't that many, keeping its visual column. Returns `false` if it was already on the
+    last line. */
+    pub fn move_cursor_page_down(&mut self, rows: usize) -> bool {
+        let (cursor_x, cursor_y) = self.get_cursor_xy();
+        let mut target_y = cursor_y;
+        for _ in 0..rows {
+            match self.next_visible_line(target_y) {
+                Some(line) => target_y = line,
+                None => break,
+            }
+        }
+        self.move_cursor_to_row(cursor_x, cursor_y, target_y)
+    }
+
+    /// Moves the cursor from `cursor_x` on `cursor_y` to the same visual column on `target_y`.
+    fn move_cursor_to_row(&mut self, cursor_x: usize, cursor_y: usize, target_y: usize) -> bool {
+        if target_y == cursor_y {
+            return false;
         }
+        let visual_x = self.get_char_column_width(cursor_x, cursor_y);
+        let new_x = self.get_char_index_from_visual_x(target_y, visual_x);
+        self.cursor_pos = self.text.line_to_char(target_y) + new_x;
+        true
     }
+
     pub fn get_cursor_xy(&self) -> (usize, usize) {
         let line_idx = self.text.char_to_line(self.cursor_pos);
         let line_start = self.text.line_to_char(line_idx);
         (self.cursor_pos - line_start, line_idx)
     }
+
+    /** The char range of the word under the cursor.
+    When the cursor sits just past the end of a word, that word is used. */
+    pub fn word_range_at_cursor(&self) -> Option<Range<usize>> {
+        let is_word_at = |idx: usize| {
+            idx < self.text.len_chars() && self.word_chars.contains(self.text.char(idx))
+        };
+        let pos = if is_word_at(self.cursor_pos) {
+            self.cursor_pos
+        } else if self.cursor_pos > 0 && is_word_at(self.cursor_pos - 1) {
+            self.cursor_pos - 1
+        } else {
+            return None;
+        };
+        let mut start = pos;
+        while start > 0 && is_word_at(start - 1) {
+            start -= 1;
+        }
+        let mut end = pos + 1;
+        while is_word_at(end) {
+            end += 1;
+        }
+        Some(start..end)
+    }
+
+    pub fn word_chars(&self) -> &WordChars {
+        &self.word_chars
+    }
+
+    pub fn set_word_chars(&mut self, word_chars: WordChars) {
+        self.word_chars = word_chars;
+    }
+
+    /// Limits the undo history to `levels` steps, dropping the oldest ones past it.
+    pub fn set_undo_levels(&mut self, levels: usize) {
+        self.undo_levels = levels;
+        self.trim_history();
+    }
+
+    pub fn word_at_cursor(&self) -> Option<String> {
+        self.word_range_at_cursor()
+            .map(|range| self.text.slice(range).to_string())
+    }
+
+    /// Counts how many times `needle` appears in the buffer. Matches never span lines.
+    pub fn count_occurrences(&self, needle: &str, options: SearchOptions) -> usize {
+        self.text
+            .lines()
+            .map(|line| find_matches(&line.to_string(), needle, options, &self.word_chars).len())
+            .sum()
+    }
+
     pub fn from_path(path: &str) -> Result<Self, BufferError> {
+        Self::from_path_with_progress(path, |_, _| {})
+    }
+
+    /** Like `from_path`, but calls `progress` with the number of bytes read
+    so far and the file's total size while it loads. */
+    pub fn from_path_with_progress(
+        path: &str,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<Self, BufferError> {
         let path = Path::new(path);
-        let file = File::open(&path);
+        let file = File::open(path);
 
         match file {
             Ok(file) => {
-                let text = Rope::from_reader(&mut BufReader::new(file))?;
+                let metadata = file.metadata().ok();
+                let total = metadata.as_ref().map_or(0, |metadata| metadata.len());
+                let disk_modified = metadata.and_then(|metadata| metadata.modified().ok());
+                let reader = ProgressReader {
+                    inner: file,
+                    read: 0,
+                    total,
+                    progress,
+                };
+                let text = Rope::from_reader(&mut BufReader::new(reader))?;
                 Ok(Buffer {
-                    text,
-                    file_path: Some(PathBuf::from(path)),
-                    status: Status::Clean,
-                    cursor_pos: 0,
-                    line_ending: if cfg!(target_os = "windows") {
-                        LineEnding::CRLF
-                    } else {
-                        LineEnding::LF
-                    },
+                    disk_modified,
+                    ..Buffer::with_text(text, Some(PathBuf::from(path)))
                 })
             }
             Err(e) => {
@@ -205,17 +555,7 @@ impl Buffer {
                         cause: Some(e),
                     })
                 } else if e.kind() == ErrorKind::NotFound {
-                    Ok(Buffer {
-                        text: Rope::new(),
-                        file_path: Some(PathBuf::from(path)),
-                        status: Status::Clean,
-                        cursor_pos: 0,
-                        line_ending: if cfg!(target_os = "windows") {
-                            LineEnding::CRLF
-                        } else {
-                            LineEnding::LF
-                        },
-                    })
+                    Ok(Buffer::new(Some(PathBuf::from(path))))
                 } else {
                     Err(BufferError {
                         message: "Can't open file".to_string(),
@@ -226,23 +566,52 @@ impl Buffer {
         }
     }
 
-    pub fn get_line(&self, idx: usize) -> RopeSlice {
+    /// The text between two char indices. The range is clamped to the buffer.
+    pub fn text_in_range(&self, range: Range<usize>) -> String {
+        let end = range.end.min(self.text.len_chars());
+        let start = range.start.min(end);
+        self.text.slice(start..end).to_string()
+    }
+
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        self.text.line_to_char(line_idx)
+    }
+
+    pub fn get_line(&self, idx: usize) -> RopeSlice<'_> {
         self.text.line(idx)
     }
 
-    pub fn bytes(&self) -> Bytes {
+    pub fn bytes(&self) -> Bytes<'_> {
         self.text.bytes()
     }
 
-    pub fn chars(&self) -> Chars {
+    pub fn chars(&self) -> Chars<'_> {
         self.text.chars()
     }
 
-    pub fn lines(&self) -> Lines {
+    pub fn lines(&self) -> Lines<'_> {
         self.text.lines()
     }
 
-    pub fn chunks(&self) -> Chunks {
+    /** Number of lines in the buffer. A line break ends the line it's on rather than
+    starting another, so a file ending in one has no empty line after it, and a file
+    that doesn't ends with its last line of text. An empty buffer has one line. */
+    pub fn line_count(&self) -> usize {
+        let len = self.text.len_chars();
+        if len > 0 && is_line_break(self.text.char(len - 1)) {
+            self.text.len_lines() - 1
+        } else {
+            self.text.len_lines()
+        }
+    }
+
+    /** Number of lines drawn and moved through: `line_count`, plus the empty line after
+    a final line break while the cursor is on it, as it is right after Enter at the end. */
+    pub fn shown_line_count(&self) -> usize {
+        self.line_count().max(self.cursor_row() + 1)
+    }
+
+    pub fn chunks(&self) -> Chunks<'_> {
         self.text.chunks()
     }
 
@@ -250,61 +619,471 @@ impl Buffer {
         self.file_path.as_ref()
     }
 
+    pub fn set_file_path(&mut self, path: PathBuf) {
+        self.project_root = find_project_root(Some(&path));
+        self.file_path = Some(path);
+        self.disk_modified = None;
+    }
+
+    /** The root of the project the file is in, found from markers like `.git`,
+    or the working directory if there's none. */
+    pub fn project_root(&self) -> &Path {
+        &self.project_root
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.len_chars() == 0
+    }
+
+    /// Whether this is a new, empty buffer with no file that hasn't been edited yet.
+    pub fn is_untouched(&self) -> bool {
+        self.file_path.is_none()
+            && self.text.len_chars() == 0
+            && matches!(self.status, Status::Clean)
+    }
+
     pub fn status(&self) -> &Status {
         &self.status
     }
 
-    pub fn save(&mut self) -> Result<String, BufferError> {
+    /** Whether the file has been modified by something else since it was loaded
+    or last saved, going by its modification time. A file that's gone, or that
+    didn't exist when the buffer was made, doesn't count as changed. */
+    pub fn changed_on_disk(&self) -> bool {
+        let (Some(path), Some(known)) = (&self.file_path, self.disk_modified) else {
+            return false;
+        };
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified != known)
+    }
+
+    /** Writes the buffer to its file, first copying what's on disk to a backup
+    if `backup` asks for one. A backup that can't be written stops the save. */
+    pub fn save(&mut self, backup: Backup) -> Result<String, BufferError> {
+        let path = match &self.file_path {
+            Some(path) => path.clone(),
+            None => {
+                return Err(BufferError {
+                    message: "No file associated with buffer".to_string(),
+                    cause: None,
+                })
+            }
+        };
         self.status = Status::Saving;
-        match &self.file_path {
-            Some(path) => {
-                let file = File::create(&path);
-                match file {
-                    Ok(mut file) => {
-                        self.text.write_to(&mut file)?;
-                        self.status = Status::Clean;
-                        Ok(format!(
-                            "Wrote {} bytes to {}",
-                            self.text.len_bytes(),
-                            path.display()
-                        ))
-                    }
-                    Err(e) => {
-                        if e.kind() == ErrorKind::PermissionDenied {
-                            Err(BufferError {
-                                message: "Can't write to file".to_string(),
-                                cause: Some(e),
-                            })
-                        } else {
-                            Err(BufferError {
-                                message: "I/O error occurred".to_string(),
-                                cause: Some(e),
-                            })
-                        }
-                    }
+        // Tidying up undoes separately from whatever was typed last
+        self.group_closed = true;
+        self.tidy_for_save();
+        match write_backup(&path, backup).and_then(|_| self.write_atomically(&path)) {
+            Ok(written) => {
+                self.status = Status::Clean;
+                self.saved_index = Some(self.undo_index);
+                self.group_closed = true;
+                self.disk_modified = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                Ok(format!("Wrote {} bytes to {}", written, path.display()))
+            }
+            Err(e) => {
+                // Nothing reached the original file, so the changes are still unsaved
+                self.status = Status::Modified;
+                Err(e)
+            }
+        }
+    }
+
+    /// Trims trailing whitespace and adds the final newline, if the buffer asks for them.
+    fn tidy_for_save(&mut self) {
+        if self.trim_trailing_whitespace {
+            self.strip_trailing_whitespace();
+        }
+        let len = self.text.len_chars();
+        if self.insert_final_newline && len > 0 && !is_line_break(self.text.char(len - 1)) {
+            self.insert_text(len, self.line_ending.as_str());
+        }
+    }
+
+    /** Removes spaces and tabs from the end of every line, keeping the cursor within
+    its line. Returns the number of lines changed. */
+    pub fn strip_trailing_whitespace(&mut self) -> usize {
+        let mut changed = 0;
+        for line_idx in (0..self.text.len_lines()).rev() {
+            let end = self.line_content_end(line_idx);
+            let line_start = self.text.line_to_char(line_idx);
+            let mut start = end;
+            while start > line_start && matches!(self.text.char(start - 1), ' ' | '\t') {
+                start -= 1;
+            }
+            if start == end {
+                continue;
+            }
+            self.remove_text(start..end);
+            if self.cursor_pos > start {
+                self.cursor_pos = self.cursor_pos.saturating_sub(end - start).max(start);
+            }
+            changed += 1;
+        }
+        if changed > 0 {
+            self.status = Status::Modified;
+        }
+        changed
+    }
+
+    /** Writes the buffer to a temporary file next to `path` and renames it
+    over the original once everything is on disk. If the write fails part way
+    through (e.g. the disk fills up) the original file is left untouched. Returns
+    the number of bytes written. */
+    fn write_atomically(&self, path: &Path) -> Result<usize, BufferError> {
+        let temp_path = temp_path_for(path);
+        let file = File::create(&temp_path).map_err(save_error)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            // Keep the original file's permissions after the rename
+            let _ = file.set_permissions(metadata.permissions());
+        }
+        let result = self
+            .write_to(BufWriter::new(file))
+            .and_then(|(file, written)| {
+                file.sync_all()?;
+                fs::rename(&temp_path, path)?;
+                Ok(written)
+            });
+        result.map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            save_error(e)
+        })
+    }
+
+    /** Writes the whole buffer to `writer`, returning the underlying writer once it's
+    flushed and the number of bytes written, line endings converted. */
+    fn write_to<W: Write>(&self, mut writer: BufWriter<W>) -> io::Result<(W, usize)> {
+        let written = match self.disk_line_ending {
+            Some(LineEnding::CRLF) => {
+                let mut written = 0;
+                for chunk in self.text.chunks() {
+                    let chunk = chunk.replace('\n', "\r\n");
+                    writer.write_all(chunk.as_bytes())?;
+                    written += chunk.len();
                 }
+                written
+            }
+            _ => {
+                self.text.write_to(&mut writer)?;
+                self.text.len_bytes()
             }
-            None => Err(BufferError {
+        };
+        let writer = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok((writer, written))
+    }
+
+    /** Converts every line ending to LF, remembering the detected ending
+    so `save` writes the file back in its original style. This is meant for a
+    buffer that was just loaded: it counts as the text on disk, so the undo
+    history starts over from it. */
+    pub fn normalize_line_endings(&mut self) {
+        let original = self.line_ending;
+        let text = self.to_string().replace("\r\n", "\n");
+        self.text = Rope::from_str(&text);
+        self.cursor_pos = self.cursor_pos.min(self.text.len_chars());
+        self.line_ending = LineEnding::LF;
+        self.disk_line_ending = Some(original);
+        self.edits.clear();
+        self.undo_index = 0;
+        self.saved_index = Some(0);
+        self.group_closed = true;
+        self.typing_at = None;
+    }
+
+    /** Loads the buffer's file again with `load` and takes on its text, along with
+    the settings detected from it. The text is replaced as one undo step, so a revert
+    can be undone like any other edit, and the buffer comes out clean. The cursor
+    stays on the same line and column where they still exist. If the file can't be
+    loaded, the buffer is left as it was. */
+    pub fn revert(
+        &mut self,
+        load: impl FnOnce(&Path) -> Result<Buffer, BufferError>,
+    ) -> Result<(), BufferError> {
+        let Some(path) = self.file_path.clone() else {
+            return Err(BufferError {
                 message: "No file associated with buffer".to_string(),
                 cause: None,
-            }),
+            });
+        };
+        let saved = load(&path)?;
+        self.revert_to(saved);
+        Ok(())
+    }
+
+    fn revert_to(&mut self, saved: Buffer) {
+        let (column, line) = self.get_cursor_xy();
+        if self.text != saved.text {
+            self.end_undo_step();
+            self.replace_range(0..self.text.len_chars(), &saved.text.to_string());
         }
+        *self = Buffer {
+            text: std::mem::take(&mut self.text),
+            folds: Vec::new(),
+            edits: std::mem::take(&mut self.edits),
+            undo_index: self.undo_index,
+            edit_group: self.edit_group,
+            group_closed: true,
+            typing_at: None,
+            saved_index: Some(self.undo_index),
+            selection_anchor: None,
+            ..saved
+        };
+        self.move_to(line, column);
     }
 
+    /** Types `c` at the cursor. A run of characters typed one after another is
+    undone as one step, split where a word ends so a sentence undoes word by word. */
     pub fn insert_char(&mut self, c: char) {
-        self.text.insert_char(self.cursor_pos, c);
+        let pos = self.cursor_pos;
+        let word_ended = c.is_whitespace() && pos > 0 && !self.text.char(pos - 1).is_whitespace();
+        if self.typing_at == Some(pos) && !word_ended {
+            self.group_closed = false;
+        }
+        self.insert_text(pos, c.encode_utf8(&mut [0; 4]));
         self.cursor_pos += 1;
+        self.typing_at = Some(self.cursor_pos);
         self.status = Status::Modified;
     }
 
+    /// Inserts `text` at a char index, recording it for undo.
+    fn insert_text(&mut self, pos: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.record(EditKind::Insert, pos, text.to_string());
+        self.apply(EditKind::Insert, pos, text);
+    }
+
+    /// Removes the text in a char range, recording it for undo.
+    fn remove_text(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let text = self.text.slice(range.clone()).to_string();
+        self.apply(EditKind::Remove, range.start, &text);
+        self.record(EditKind::Remove, range.start, text);
+    }
+
+    /** Makes an edit to the rope without recording it. Everything else that changes
+    the text goes through `insert_text` and `remove_text`, or undo and redo, so the
+    edit log always describes the rope. */
+    fn apply(&mut self, kind: EditKind, pos: usize, text: &str) {
+        match kind {
+            EditKind::Insert => self.text.insert(pos, text),
+            EditKind::Remove => self.text.remove(pos..pos + text.chars().count()),
+        }
+    }
+
+    fn record(&mut self, kind: EditKind, pos: usize, text: String) {
+        let new_group = self.group_closed;
+        if new_group {
+            self.edit_group += 1;
+            self.group_closed = false;
+        }
+        self.edits.truncate(self.undo_index);
+        // Redoing back to the saved text is no longer possible once the redo history is gone
+        if self
+            .saved_index
+            .is_some_and(|saved| saved > self.undo_index)
+        {
+            self.saved_index = None;
+        }
+        self.edits.push(EditOp {
+            kind,
+            pos,
+            text,
+            cursor_before: self.cursor_pos,
+            group: self.edit_group,
+        });
+        self.undo_index += 1;
+        if new_group {
+            self.trim_history();
+        }
+        self.typing_at = None;
+        // Char indices after the edit have moved, so a selection would no longer cover the same text
+        self.selection_anchor = None;
+    }
+
+    /** Drops the oldest undo steps while there are more than `undo_levels` that can
+    be undone. The saved text is forgotten if it was only reachable through them. */
+    fn trim_history(&mut self) {
+        let undoable = &self.edits[..self.undo_index];
+        let mut steps = 0;
+        let mut keep_from = 0;
+        for idx in (0..undoable.len()).rev() {
+            // Count each step at its last edit
+            if undoable
+                .get(idx + 1)
+                .is_none_or(|next| next.group != undoable[idx].group)
+            {
+                steps += 1;
+                if steps > self.undo_levels {
+                    keep_from = idx + 1;
+                    break;
+                }
+            }
+        }
+        if keep_from == 0 {
+            return;
+        }
+        self.edits.drain(..keep_from);
+        self.undo_index -= keep_from;
+        self.saved_index = self
+            .saved_index
+            .and_then(|saved| saved.checked_sub(keep_from));
+    }
+
+    /// Starts selecting from the cursor, unless a selection is already being extended.
+    pub fn start_selection(&mut self) {
+        if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.cursor_pos);
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection_anchor = None;
+    }
+
+    /** The selected char range as `(start, end)`, start first whichever way it was
+    made, or `None` if nothing is selected. Any edit ends the selection. */
+    pub fn selection_range(&self) -> Option<(usize, usize)> {
+        let anchor = self.selection_anchor?.min(self.text.len_chars());
+        (anchor != self.cursor_pos)
+            .then(|| (anchor.min(self.cursor_pos), anchor.max(self.cursor_pos)))
+    }
+
+    /// The selected text, if there's a selection.
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection_range()?;
+        Some(self.text.slice(start..end).to_string())
+    }
+
+    /** How much is selected, as the number of lines the selection touches and the
+    number of characters in it, or `None` if nothing is selected. */
+    pub fn selection_size(&self) -> Option<(usize, usize)> {
+        let (start, end) = self.selection_range()?;
+        Some((self.selected_lines().len(), end - start))
+    }
+
+    /** The whole lines the selection touches, or just the cursor's line when nothing
+    is selected. A selection ending at the very start of a line leaves that line out. */
+    pub fn selected_lines(&self) -> Range<usize> {
+        let Some((start, end)) = self.selection_range() else {
+            let row = self.cursor_row();
+            return row..row + 1;
+        };
+        let first = self.text.char_to_line(start);
+        let last = self.text.char_to_line(end);
+        if last > first && self.text.line_to_char(last) == end {
+            first..last
+        } else {
+            first..last + 1
+        }
+    }
+
+    /** Deletes the selected text as one edit, leaving the cursor where it started.
+    Returns `false` if nothing was selected. */
+    pub fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection_range() else {
+            return false;
+        };
+        self.replace_range(start..end, "");
+        true
+    }
+
+    /** Deletes everything outside the selection as one edit, leaving only the selected
+    text with the cursor at its start. Returns `false` if nothing was selected. */
+    pub fn keep_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection_range() else {
+            return false;
+        };
+        self.replace_range(end..self.text.len_chars(), "");
+        self.replace_range(0..start, "");
+        self.cursor_pos = 0;
+        true
+    }
+
+    /** Ends the current undo step, so the next edit starts a new one. The editor
+    calls this after every key or command, making each one undo as a unit. */
+    pub fn end_undo_step(&mut self) {
+        self.group_closed = true;
+    }
+
+    /** Reverts the most recent undo step, putting the cursor back where it was
+    before it. Returns `None` if there's nothing to undo. */
+    pub fn undo(&mut self) -> Option<()> {
+        let group = self.edits[..self.undo_index].last()?.group;
+        while let Some(op) = self.edits[..self.undo_index].last() {
+            if op.group != group {
+                break;
+            }
+            let (kind, pos, cursor_before) = (op.kind.inverse(), op.pos, op.cursor_before);
+            let text = op.text.clone();
+            self.apply(kind, pos, &text);
+            self.cursor_pos = cursor_before;
+            self.undo_index -= 1;
+        }
+        self.after_history_move();
+        Some(())
+    }
+
+    /** Applies the most recently undone step again, leaving the cursor after it.
+    Returns `None` if there's nothing to redo. */
+    pub fn redo(&mut self) -> Option<()> {
+        let group = self.edits.get(self.undo_index)?.group;
+        while let Some(op) = self.edits.get(self.undo_index) {
+            if op.group != group {
+                break;
+            }
+            let (kind, pos, text) = (op.kind, op.pos, op.text.clone());
+            self.apply(kind, pos, &text);
+            self.cursor_pos = match kind {
+                EditKind::Insert => pos + text.chars().count(),
+                EditKind::Remove => pos,
+            };
+            self.undo_index += 1;
+        }
+        self.after_history_move();
+        Some(())
+    }
+
+    fn after_history_move(&mut self) {
+        self.cursor_pos = self.cursor_pos.min(self.text.len_chars());
+        // Lines may have come and gone under the folds, so they're dropped
+        self.folds.clear();
+        self.group_closed = true;
+        self.typing_at = None;
+        self.selection_anchor = None;
+        self.status = if self.saved_index == Some(self.undo_index) {
+            Status::Clean
+        } else {
+            Status::Modified
+        };
+    }
+
+    /** Replaces the character under the cursor with `c` and moves past it, as in
+    overwrite mode. At the end of a line there's nothing to replace, so `c` is inserted. */
+    pub fn overwrite_char(&mut self, c: char) {
+        let row = self.cursor_row();
+        if self.cursor_pos < self.line_content_end(row) {
+            self.remove_text(self.cursor_pos..self.cursor_pos + 1);
+        }
+        self.insert_char(c);
+    }
+
     pub fn delete_char(&mut self) -> crossterm::Result<()> {
         if self.cursor_pos > 0 {
             let start = self.cursor_pos.saturating_sub(self.line_ending.len());
             if self.text.slice(start..self.cursor_pos) == self.line_ending.as_str() {
-                self.text.remove(start..self.cursor_pos);
+                let row = self.cursor_row();
+                self.remove_text(start..self.cursor_pos);
                 self.cursor_pos = start;
+                self.adjust_folds(row - 1, row, -1);
             } else {
-                self.text.remove((self.cursor_pos - 1)..self.cursor_pos);
+                self.remove_text((self.cursor_pos - 1)..self.cursor_pos);
                 self.cursor_pos -= 1;
             }
             // I don't know how efficient this is, but it fixes the issue where
@@ -320,14 +1099,1720 @@ impl Buffer {
         Ok(())
     }
 
-    pub fn insert_newline(&mut self) -> crossterm::Result<()> {
-        self.text.insert(self.cursor_pos, self.line_ending.as_str());
-        // How much to move to the right to be in front of the newline character(s).
-        self.cursor_pos += self.line_ending.len();
-        execute!(
-            std::io::stdout(),
-            terminal::Clear(ClearType::FromCursorDown)
-        )?;
+    /** Deletes from the cursor back to the start of the word before it, as
+    `move_cursor_word_left` would move. At the start of a line it deletes the line
+    ending instead, joining the line to the one before it like `delete_char`. */
+    pub fn delete_word_backward(&mut self) -> crossterm::Result<()> {
+        let line_start = self.text.line_to_char(self.cursor_row());
+        if self.cursor_pos == line_start {
+            return self.delete_char();
+        }
+        let start = self.word_start_before(line_start);
+        self.remove_text(start..self.cursor_pos);
+        self.cursor_pos = start;
+        self.status = Status::Modified;
         Ok(())
     }
+
+    pub fn indent_style(&self) -> Option<IndentStyle> {
+        self.indent_style
+    }
+
+    /// Overrides the detected indentation, e.g. from a modeline.
+    pub fn set_indent_style(&mut self, style: IndentStyle) {
+        self.indent_style = Some(style);
+    }
+
+    /** Sets the ending used for new lines. When endings were normalized on load,
+    this is the ending the file is saved with instead. */
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        if self.disk_line_ending.is_some() {
+            self.disk_line_ending = Some(ending);
+        } else {
+            self.line_ending = ending;
+        }
+    }
+
+    pub fn set_trim_trailing_whitespace(&mut self, trim: bool) {
+        self.trim_trailing_whitespace = trim;
+    }
+
+    pub fn set_insert_final_newline(&mut self, insert: bool) {
+        self.insert_final_newline = insert;
+    }
+
+    /// Number of columns between tab stops.
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width.max(1);
+    }
+
+    /// Where tabs stop: the ones set with `set_tab_stops`, or every `tab_width` columns.
+    pub fn tab_stops(&self) -> TabStops {
+        self.tab_stops
+            .clone()
+            .unwrap_or_else(|| TabStops::uniform(self.tab_width))
+    }
+
+    /** Sets non-uniform tab stops, or goes back to uniform ones with `None`. Only how
+    tabs are shown changes. Indentation still counts in `tab_width` columns. */
+    pub fn set_tab_stops(&mut self, stops: Option<TabStops>) {
+        self.tab_stops = stops;
+    }
+
+    /** Inserts one level of indentation at the cursor: a tab, or enough
+    spaces to reach the next multiple of the indent width. */
+    pub fn insert_indent(&mut self, style: IndentStyle) {
+        match style {
+            IndentStyle::Tabs => self.insert_char('\t'),
+            IndentStyle::Spaces(width) => {
+                let width = width.max(1);
+                let spaces = width - self.get_visual_cursor_x() % width;
+                self.insert_text(self.cursor_pos, &" ".repeat(spaces));
+                self.cursor_pos += spaces;
+                self.status = Status::Modified;
+            }
+        }
+    }
+
+    /// Rewrites the leading indentation of every line with spaces. Returns the number of lines changed.
+    pub fn tabs_to_spaces(&mut self, width: usize) -> usize {
+        self.retab(width, false)
+    }
+
+    /** Rewrites the leading indentation of every line with tabs, keeping any
+    leftover spaces that don't fill a whole level. Returns the number of lines changed. */
+    pub fn spaces_to_tabs(&mut self, width: usize) -> usize {
+        self.retab(width, true)
+    }
+
+    fn retab(&mut self, width: usize, use_tabs: bool) -> usize {
+        let width = width.max(1);
+        let (cursor_x, cursor_y) = self.get_cursor_xy();
+        let changed = (0..self.text.len_lines())
+            .filter(|line_idx| self.retab_line(*line_idx, width, use_tabs))
+            .count();
+        if changed > 0 {
+            self.status = Status::Modified;
+            self.indent_style = Some(if use_tabs {
+                IndentStyle::Tabs
+            } else {
+                IndentStyle::Spaces(width)
+            });
+            // Lines may have changed length, so keep the cursor on its line but within it
+            self.cursor_pos =
+                (self.text.line_to_char(cursor_y) + cursor_x).min(self.line_content_end(cursor_y));
+        }
+        changed
+    }
+
+    /** Finds the first match of `query` starting at or after the char index `from`,
+    wrapping around to the start of the buffer. Returns the char index where it starts. */
+    pub fn find_next(&self, query: &str, from: usize, options: SearchOptions) -> Option<usize> {
+        let from = from.min(self.text.len_chars());
+        let from_line = self.text.char_to_line(from);
+        let line_count = self.text.len_lines();
+        // The starting line is visited twice: first after `from`, then before it once wrapped
+        for step in 0..=line_count {
+            let line_idx = (from_line + step) % line_count;
+            let line_start = self.text.line_to_char(line_idx);
+            let found = self.line_matches(line_idx, query, options).find(|start| {
+                let start = line_start + start;
+                match step {
+                    0 => start >= from,
+                    _ if step == line_count => start < from,
+                    _ => true,
+                }
+            });
+            if let Some(start) = found {
+                return Some(line_start + start);
+            }
+        }
+        None
+    }
+
+    /** Finds the last match of `query` starting before the char index `from`,
+    wrapping around to the end of the buffer. Returns the char index where it starts. */
+    pub fn find_prev(&self, query: &str, from: usize, options: SearchOptions) -> Option<usize> {
+        let from = from.min(self.text.len_chars());
+        let from_line = self.text.char_to_line(from);
+        let line_count = self.text.len_lines();
+        for step in 0..=line_count {
+            let line_idx = (from_line + line_count - step % line_count) % line_count;
+            let line_start = self.text.line_to_char(line_idx);
+            let found = self
+                .line_matches(line_idx, query, options)
+                .filter(|start| {
+                    let start = line_start + start;
+                    match step {
+                        0 => start < from,
+                        _ if step == line_count => start >= from,
+                        _ => true,
+                    }
+                })
+                .last();
+            if let Some(start) = found {
+                return Some(line_start + start);
+            }
+        }
+        None
+    }
+
+    /** Replaces the first match of `query` at or after the cursor, wrapping around
+    like `find_next`, and leaves the cursor just past the replacement. Returns `false`
+    if there's no match. */
+    pub fn replace_next(&mut self, query: &str, replacement: &str, options: SearchOptions) -> bool {
+        let Some(start) = self.find_next(query, self.cursor_pos, options) else {
+            return false;
+        };
+        let line_idx = self.text.char_to_line(start);
+        let line_start = self.text.line_to_char(line_idx);
+        let found = find_matches(
+            &self.text.line(line_idx).to_string(),
+            query,
+            options,
+            &self.word_chars,
+        )
+        .into_iter()
+        .find(|range| line_start + range.start == start);
+        let Some(range) = found else {
+            return false;
+        };
+        self.replace_range(
+            line_start + range.start..line_start + range.end,
+            replacement,
+        );
+        true
+    }
+
+    /// Start columns of the matches of `query` on a single line.
+    fn line_matches(
+        &self,
+        line_idx: usize,
+        query: &str,
+        options: SearchOptions,
+    ) -> impl Iterator<Item = usize> {
+        find_matches(
+            &self.text.line(line_idx).to_string(),
+            query,
+            options,
+            &self.word_chars,
+        )
+        .into_iter()
+        .map(|range| range.start)
+    }
+
+    pub fn cursor_pos(&self) -> usize {
+        self.cursor_pos
+    }
+
+    /// Number of chars in the whole buffer.
+    pub fn len_chars(&self) -> usize {
+        self.text.len_chars()
+    }
+
+    /// Byte offset of the cursor in the buffer, as reported by compilers and hex dumps.
+    pub fn cursor_byte(&self) -> usize {
+        self.text.char_to_byte(self.cursor_pos)
+    }
+
+    /** Describes the character under the cursor and its code point, e.g. `'→' U+2192`.
+    Line endings show as `<EOL>` and the end of the buffer as `<EOF>`. */
+    pub fn describe_char_at_cursor(&self) -> String {
+        if self.cursor_pos >= self.text.len_chars() {
+            return "<EOF>".to_string();
+        }
+        let ch = self.text.char(self.cursor_pos);
+        if is_line_break(ch) {
+            return "<EOL>".to_string();
+        }
+        format!("'{}' U+{:04X}", ch.escape_debug(), ch as u32)
+    }
+
+    /// Moves the cursor to a char index, unfolding any fold that hides it.
+    pub fn set_cursor_pos(&mut self, pos: usize) {
+        self.cursor_pos = pos.min(self.text.len_chars());
+        let row = self.cursor_row();
+        self.folds
+            .retain(|fold| !(fold.start < row && row < fold.end));
+    }
+
+    /// Moves the cursor to a line and column, both counting from 0 and clamped to the buffer.
+    pub fn move_to(&mut self, line: usize, column: usize) {
+        let line = line.min(self.line_count() - 1);
+        let line_start = self.text.line_to_char(line);
+        let pos = (line_start + column).min(self.line_content_end(line));
+        self.set_cursor_pos(pos);
+    }
+
+    /// The fold whose header is `line`, if any.
+    pub fn fold_at(&self, line: usize) -> Option<&Range<usize>> {
+        self.folds.iter().find(|fold| fold.start == line)
+    }
+
+    /// The next line after `line` that isn't hidden inside a fold.
+    pub fn next_visible_line(&self, line: usize) -> Option<usize> {
+        let next = self.fold_at(line).map_or(line + 1, |fold| fold.end);
+        (next < self.shown_line_count()).then_some(next)
+    }
+
+    /// The closest line before `line` that isn't hidden inside a fold.
+    pub fn prev_visible_line(&self, line: usize) -> Option<usize> {
+        let prev = line.checked_sub(1)?;
+        Some(
+            self.folds
+                .iter()
+                .find(|fold| fold.start < prev && prev < fold.end)
+                .map_or(prev, |fold| fold.start),
+        )
+    }
+
+    /// How many lines in `from..to` are shown on screen.
+    pub fn visible_lines_between(&self, from: usize, to: usize) -> usize {
+        let hidden: usize = self
+            .folds
+            .iter()
+            .map(|fold| {
+                let start = (fold.start + 1).max(from);
+                let end = fold.end.min(to);
+                end.saturating_sub(start)
+            })
+            .sum();
+        to.saturating_sub(from) - hidden
+    }
+
+    /** Folds every block indented deeper than `level` indentation levels under the
+    line that introduces it, replacing any existing folds. `width` is the number of
+    columns in one level. Returns the number of folds made. */
+    pub fn fold_to_level(&mut self, level: usize, width: usize) -> usize {
+        let line_count = self.text.len_lines();
+        let levels: Vec<Option<usize>> = (0..line_count)
+            .map(|line| indent_level(self.text.line(line), width))
+            .collect();
+        let is_deep = |line: usize| levels[line].is_none_or(|line_level| line_level > level);
+        self.folds.clear();
+        let mut line = 0;
+        while line < line_count {
+            if levels[line].is_none_or(|line_level| line_level <= level) {
+                line += 1;
+                continue;
+            }
+            let header = (0..line).rev().find(|idx| levels[*idx].is_some());
+            let mut last_deep = line;
+            while line < line_count && is_deep(line) {
+                if levels[line].is_some() {
+                    last_deep = line;
+                }
+                line += 1;
+            }
+            // Blank lines after the block aren't part of it
+            if let Some(header) = header {
+                self.folds.push(header..last_deep + 1);
+            }
+        }
+        // Keep the cursor on a visible line
+        let row = self.cursor_row();
+        if let Some(fold) = self
+            .folds
+            .iter()
+            .find(|fold| fold.start < row && row < fold.end)
+        {
+            self.cursor_pos = self.text.line_to_char(fold.start);
+        }
+        self.folds.len()
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folds.clear();
+    }
+
+    /** Keeps folds in step with an edit that added (or removed) `delta` lines
+    between `first` and `last`. Folds touching the edited lines are opened. */
+    fn adjust_folds(&mut self, first: usize, last: usize, delta: isize) {
+        self.folds
+            .retain(|fold| !(fold.start <= last && first < fold.end));
+        for fold in self.folds.iter_mut().filter(|fold| fold.start > last) {
+            fold.start = fold.start.saturating_add_signed(delta);
+            fold.end = fold.end.saturating_add_signed(delta);
+        }
+    }
+
+    /** Replaces every match of `query` with `replacement`, calling `progress`
+    with the number of lines processed so far and the total. When `scope` is given, only
+    matches entirely inside that char range are replaced. The cursor keeps its place
+    relative to the surrounding text. Returns the number of replacements made. */
+    pub fn replace_all(
+        &mut self,
+        query: &str,
+        replacement: &str,
+        options: SearchOptions,
+        scope: Option<Range<usize>>,
+        mut progress: impl FnMut(usize, usize),
+    ) -> usize {
+        let scope = scope.unwrap_or(0..self.text.len_chars());
+        let total_lines = self.text.len_lines();
+        let replacement_len = replacement.chars().count();
+        let mut cursor_pos = self.cursor_pos;
+        let mut count = 0;
+        // Work from the bottom up so the char indices of earlier lines stay valid
+        for (done, line_idx) in (0..total_lines).rev().enumerate() {
+            let line_start = self.text.line_to_char(line_idx);
+            let matches = find_matches(
+                &self.text.line(line_idx).to_string(),
+                query,
+                options,
+                &self.word_chars,
+            );
+            for range in matches.into_iter().rev() {
+                let start = line_start + range.start;
+                let end = line_start + range.end;
+                if start < scope.start || end > scope.end {
+                    continue;
+                }
+                self.remove_text(start..end);
+                self.insert_text(start, replacement);
+                if cursor_pos >= end {
+                    cursor_pos = cursor_pos - (end - start) + replacement_len;
+                } else if cursor_pos > start {
+                    cursor_pos = start;
+                }
+                count += 1;
+            }
+            progress(done + 1, total_lines);
+        }
+        if count > 0 {
+            self.cursor_pos = cursor_pos.min(self.text.len_chars());
+            self.status = Status::Modified;
+        }
+        count
+    }
+
+    /** Rewrites the leading indentation of a single line with tabs or spaces,
+    `width` columns per tab. Returns `false` if the line was already written that way. */
+    fn retab_line(&mut self, line_idx: usize, width: usize, use_tabs: bool) -> bool {
+        let line = self.text.line(line_idx);
+        let indent_len = leading_whitespace_len(line);
+        let mut columns = 0;
+        for ch in line.chars().take(indent_len) {
+            columns += match ch {
+                '\t' => width - columns % width,
+                _ => 1,
+            };
+        }
+        let new_indent = if use_tabs {
+            format!(
+                "{}{}",
+                "\t".repeat(columns / width),
+                " ".repeat(columns % width)
+            )
+        } else {
+            " ".repeat(columns)
+        };
+        if line.slice(..indent_len) == new_indent.as_str() {
+            return false;
+        }
+        let line_start = self.text.line_to_char(line_idx);
+        self.remove_text(line_start..line_start + indent_len);
+        self.insert_text(line_start, &new_indent);
+        true
+    }
+
+    /** When the cursor is within the current line's leading whitespace, switches that
+    whitespace between tabs and spaces, keeping the cursor on the same screen column.
+    Returns `false` if the cursor isn't in the indentation. */
+    pub fn toggle_line_indent(&mut self) -> bool {
+        let (cursor_x, cursor_y) = self.get_cursor_xy();
+        let line = self.text.line(cursor_y);
+        let indent_len = leading_whitespace_len(line);
+        if indent_len == 0 || cursor_x > indent_len {
+            return false;
+        }
+        let use_tabs = !line.chars().take(indent_len).any(|ch| ch == '\t');
+        let visual_x = self.get_visual_cursor_x();
+        if self.retab_line(cursor_y, self.tab_width, use_tabs) {
+            self.status = Status::Modified;
+            self.cursor_pos = self.text.line_to_char(cursor_y)
+                + self.get_char_index_from_visual_x(cursor_y, visual_x);
+        }
+        true
+    }
+
+    pub fn insert_newline(&mut self) -> crossterm::Result<()> {
+        let row = self.cursor_row();
+        self.adjust_folds(row, row, 1);
+        self.insert_text(self.cursor_pos, self.line_ending.as_str());
+        // How much to move to the right to be in front of the newline character(s).
+        self.cursor_pos += self.line_ending.len();
+        execute!(
+            std::io::stdout(),
+            terminal::Clear(ClearType::FromCursorDown)
+        )?;
+        Ok(())
+    }
+
+    /** Starts a new line indented like the current one, dropping any spaces that
+    followed the cursor. With `smart`, the new line goes one level deeper after an
+    opening bracket, and when the matching closing bracket follows the cursor it's
+    moved to a line of its own below, back at the current indentation. */
+    pub fn insert_newline_indented(
+        &mut self,
+        style: IndentStyle,
+        smart: bool,
+    ) -> crossterm::Result<()> {
+        let (column, row) = self.get_cursor_xy();
+        let line = self.text.line(row);
+        let indent: String = line
+            .chars()
+            .take(column)
+            .take_while(|ch| matches!(ch, ' ' | '\t'))
+            .collect();
+        let closer = line
+            .chars_at(column)
+            .reversed()
+            .find(|ch| !matches!(ch, ' ' | '\t'))
+            .and_then(closing_bracket)
+            .filter(|_| smart);
+        let trailing_spaces = line
+            .chars()
+            .skip(column)
+            .take_while(|ch| matches!(ch, ' ' | '\t'))
+            .count();
+        let closes = closer.is_some() && line.get_char(column + trailing_spaces) == closer;
+
+        self.remove_text(self.cursor_pos..self.cursor_pos + trailing_spaces);
+        self.insert_newline()?;
+        self.insert_str(&indent);
+        if closer.is_some() {
+            self.insert_indent(style);
+        }
+        if closes {
+            let pos = self.cursor_pos;
+            self.insert_newline()?;
+            self.insert_str(&indent);
+            self.cursor_pos = pos;
+        }
+        self.status = Status::Modified;
+        Ok(())
+    }
+
+    /** Removes one level of indentation before the cursor if there's nothing else
+    before it on the line, so a closing bracket typed there lines up with the line
+    that opened its block. Returns whether anything was removed. */
+    pub fn dedent_before_cursor(&mut self, style: IndentStyle) -> bool {
+        let (column, row) = self.get_cursor_xy();
+        let line = self.text.line(row);
+        if column == 0
+            || !line
+                .slice(..column)
+                .chars()
+                .all(|ch| matches!(ch, ' ' | '\t'))
+        {
+            return false;
+        }
+        let remove = if line.char(column - 1) == '\t' {
+            1
+        } else {
+            let width = match style {
+                IndentStyle::Spaces(width) => width.max(1),
+                IndentStyle::Tabs => self.tab_width,
+            };
+            // Back to the previous indent stop, but not into a tab
+            let to_stop = (self.get_visual_cursor_x() - 1) % width + 1;
+            let spaces = line
+                .chars_at(column)
+                .reversed()
+                .take_while(|ch| *ch == ' ')
+                .count();
+            to_stop.min(spaces)
+        };
+        self.remove_text(self.cursor_pos - remove..self.cursor_pos);
+        self.cursor_pos -= remove;
+        self.status = Status::Modified;
+        true
+    }
+
+    /// Inserts `text` at the cursor and moves past it.
+    pub fn insert_str(&mut self, text: &str) {
+        let row = self.cursor_row();
+        let new_lines = text.chars().filter(|ch| *ch == '\n').count();
+        if new_lines > 0 {
+            self.adjust_folds(row, row, new_lines as isize);
+        }
+        self.insert_text(self.cursor_pos, text);
+        self.cursor_pos += text.chars().count();
+        self.status = Status::Modified;
+    }
+
+    /** The lines from `lines.start` up to but not including `lines.end`, each with
+    its line ending. The range is clamped to the buffer. */
+    pub fn lines_in_range(&self, lines: Range<usize>) -> impl Iterator<Item = RopeSlice<'_>> {
+        let end_line = lines.end.min(self.text.len_lines());
+        let start_line = lines.start.min(end_line);
+        self.text.lines_at(start_line).take(end_line - start_line)
+    }
+
+    /** The char range covering the lines from `start_line` up to but not including
+    `end_line`, line endings included. The lines are clamped to the buffer. */
+    pub fn line_range_chars(&self, start_line: usize, end_line: usize) -> Range<usize> {
+        let end_line = end_line.min(self.text.len_lines());
+        let start_line = start_line.min(end_line);
+        self.text.line_to_char(start_line)..self.text.line_to_char(end_line)
+    }
+
+    /** The text of the whole lines in `lines`, ending in a line ending even when
+    the last of them has none, as it's yanked linewise. */
+    pub fn lines_text(&self, lines: Range<usize>) -> String {
+        let mut text = self
+            .text
+            .slice(self.line_range_chars(lines.start, lines.end))
+            .to_string();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push_str(self.line_ending.as_str());
+        }
+        text
+    }
+
+    /** Deletes the whole lines in `lines`, leaving the cursor at the start of the
+    line that moves up into their place. Returns their text as `lines_text` does. */
+    pub fn remove_lines(&mut self, lines: Range<usize>) -> String {
+        let text = self.lines_text(lines.clone());
+        let end_line = lines.end.min(self.text.len_lines());
+        let start_line = lines.start.min(end_line);
+        let Range { mut start, end } = self.line_range_chars(start_line, end_line);
+        if start == end {
+            return text;
+        }
+        // The last line has no ending of its own, so take the one before it instead
+        if end == self.text.len_chars() && !is_line_break(self.text.char(end - 1)) && start > 0 {
+            start = self.line_content_end(start_line - 1);
+        }
+        self.adjust_folds(start_line, end_line, -((end_line - start_line) as isize));
+        self.remove_text(start..end);
+        let line = start_line.min(self.text.len_lines() - 1);
+        self.cursor_pos = self.text.line_to_char(line);
+        self.status = Status::Modified;
+        text
+    }
+
+    /** Inserts a copy of the whole lines in `lines` right after them, moving the
+    cursor down onto the copy at the same column. */
+    pub fn duplicate_lines(&mut self, lines: Range<usize>) {
+        let (column, row) = self.get_cursor_xy();
+        let text = self.lines_text(lines.clone());
+        // Going in above the lines keeps the buffer's last line without an ending if it had none
+        self.cursor_pos = self
+            .text
+            .line_to_char(lines.start.min(self.text.len_lines()));
+        self.insert_str(&text);
+        let copied = lines.end.saturating_sub(lines.start);
+        self.move_to(row + copied, column);
+    }
+
+    /// Replaces the text between two char indices, leaving the cursor just past the new text.
+    pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        let end = range.end.min(self.text.len_chars());
+        let start = range.start.min(end);
+        let first = self.text.char_to_line(start);
+        let last = self.text.char_to_line(end);
+        let new_lines = text.chars().filter(|ch| *ch == '\n').count();
+        let delta = new_lines as isize - (last - first) as isize;
+        if delta != 0 || first != last {
+            self.adjust_folds(first, last, delta);
+        }
+        self.remove_text(start..end);
+        self.insert_text(start, text);
+        self.cursor_pos = start + text.chars().count();
+        self.status = Status::Modified;
+    }
+
+    /** Adds `delta` to the number under or after the cursor on its line, leaving the
+    cursor on its last digit. Returns `false` if there's no number there, or the
+    result doesn't fit. */
+    pub fn increment_number(&mut self, delta: i64) -> bool {
+        let (column, row) = self.get_cursor_xy();
+        let line_start = self.text.line_to_char(row);
+        let line = self
+            .text
+            .slice(line_start..self.line_content_end(row))
+            .to_string();
+        let Some(range) = find_number(&line, column) else {
+            return false;
+        };
+        let number: String = line.chars().skip(range.start).take(range.len()).collect();
+        let Some(replacement) = add_to_number(&number, delta) else {
+            return false;
+        };
+        self.replace_range(
+            line_start + range.start..line_start + range.end,
+            &replacement,
+        );
+        self.cursor_pos -= 1;
+        true
+    }
+
+    /** Comments out the whole lines in `lines`, or uncomments them if they already are.
+    Line comments are used when the language has them, unless `prefer_block` is set,
+    and a block comment wrapping the lines is removed as well as added. Returns
+    `true` if the lines were commented out. */
+    pub fn toggle_comment(
+        &mut self,
+        lines: Range<usize>,
+        syntax: CommentSyntax,
+        prefer_block: bool,
+    ) -> bool {
+        let (column, row) = self.get_cursor_xy();
+        let last = lines
+            .end
+            .saturating_sub(1)
+            .max(lines.start)
+            .min(self.line_count() - 1);
+        let start = self.text.line_to_char(lines.start.min(last));
+        let end = self.line_content_end(last);
+        let old = self.text.slice(start..end).to_string();
+
+        let block = syntax
+            .block
+            .filter(|_| prefer_block || syntax.line.is_none());
+        let (new, commented) = if let Some(uncommented) = syntax
+            .block
+            .and_then(|(open, close)| unwrap_block_comment(&old, open, close))
+        {
+            (uncommented, false)
+        } else if let Some(uncommented) = syntax
+            .line
+            .and_then(|token| remove_line_comments(&old, token))
+        {
+            (uncommented, false)
+        } else if let Some((open, close)) = block {
+            (wrap_block_comment(&old, open, close), true)
+        } else if let Some(token) = syntax.line {
+            (add_line_comments(&old, token), true)
+        } else {
+            return false;
+        };
+
+        let line_len = |text: &str, idx: usize| {
+            text.split('\n')
+                .nth(idx)
+                .map_or(0, |line| line.chars().count())
+        };
+        let cursor_line = row.saturating_sub(lines.start);
+        let shift = line_len(&new, cursor_line) as isize - line_len(&old, cursor_line) as isize;
+        self.remove_text(start..end);
+        self.insert_text(start, &new);
+        self.status = Status::Modified;
+        self.move_to(row, column.saturating_add_signed(shift));
+        commented
+    }
+
+    /** Inserts `text` with its indentation adjusted to where it lands, keeping the
+    lines' indentation relative to the first one. Linewise text goes below the
+    cursor's line and lines up with it, anything else starts at the cursor. */
+    pub fn insert_reindented(&mut self, text: &str, style: IndentStyle) {
+        if text.ends_with('\n') {
+            let line = self.text.line(self.cursor_row());
+            let target = indent_width(
+                line.chars().take(leading_whitespace_len(line)),
+                self.tab_width,
+            );
+            let text = reindent(text, target, style, self.tab_width);
+            self.insert_lines_below(&text);
+        } else {
+            let target = self.get_visual_cursor_x();
+            let text = reindent(text, target, style, self.tab_width);
+            // The cursor already sits at the right column for the first line
+            self.insert_str(text.trim_start_matches([' ', '\t']));
+        }
+    }
+
+    /// Converts every line ending in `text` to the one this buffer uses.
+    pub fn with_line_endings(&self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        match self.line_ending {
+            LineEnding::LF => text,
+            LineEnding::CRLF => text.replace('\n', "\r\n"),
+        }
+    }
+
+    /** Inserts text yanked linewise below the cursor's line, leaving the cursor at
+    the start of the first inserted line. */
+    pub fn insert_lines_below(&mut self, text: &str) {
+        let line = self.cursor_row() + 1;
+        self.cursor_pos = self.text.line_to_char(line.min(self.text.len_lines()));
+        if line >= self.text.len_lines() && self.cursor_pos > 0 {
+            // The buffer doesn't end in a line ending, so the text needs one in front
+            if !is_line_break(self.text.char(self.cursor_pos - 1)) {
+                self.insert_str(self.line_ending.as_str());
+            }
+        }
+        let start = self.cursor_pos;
+        self.insert_str(text);
+        self.cursor_pos = start;
+    }
+}
+
+/// The bracket that closes `open`, if it's an opening bracket.
+fn closing_bracket(open: char) -> Option<char> {
+    match open {
+        '{' => Some('}'),
+        '[' => Some(']'),
+        '(' => Some(')'),
+        _ => None,
+    }
+}
+
+/** Columns that tabs advance to, counting from 0. Past the last stop listed, stops
+repeat at the interval between the last two, so a single stop makes them uniform. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TabStops {
+    stops: Vec<usize>,
+}
+
+impl TabStops {
+    pub fn uniform(width: usize) -> Self {
+        Self {
+            stops: vec![width.max(1)],
+        }
+    }
+
+    /// Stops at the given columns, or `None` if the list is empty or doesn't strictly increase from above 0.
+    pub fn new(stops: Vec<usize>) -> Option<Self> {
+        let increasing = stops.first().is_some_and(|first| *first > 0)
+            && stops.windows(2).all(|pair| pair[0] < pair[1]);
+        increasing.then_some(Self { stops })
+    }
+
+    /// Reads a list like `8,16,40`.
+    pub fn parse(list: &str) -> Option<Self> {
+        let stops = list
+            .split(',')
+            .map(|stop| stop.trim().parse().ok())
+            .collect::<Option<Vec<usize>>>()?;
+        Self::new(stops)
+    }
+
+    /// The first stop after `column`.
+    pub fn next_stop(&self, column: usize) -> usize {
+        if let Some(stop) = self.stops.iter().find(|stop| **stop > column) {
+            return *stop;
+        }
+        let last = self.stops[self.stops.len() - 1];
+        let interval = match self.stops.len() {
+            1 => last,
+            len => last - self.stops[len - 2],
+        };
+        last + ((column - last) / interval + 1) * interval
+    }
+
+    /// How many columns a tab starting at `column` takes up.
+    pub fn width_at(&self, column: usize) -> usize {
+        self.next_stop(column) - column
+    }
+}
+
+impl fmt::Display for TabStops {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stops: Vec<String> = self.stops.iter().map(usize::to_string).collect();
+        f.write_str(&stops.join(","))
+    }
+}
+
+/// The whole text of the buffer, so `to_string()` gives its contents.
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.text.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// An unsaved buffer holding `text`, e.g. for tests and benchmarks.
+impl From<&str> for Buffer {
+    fn from(text: &str) -> Self {
+        Buffer::with_text(Rope::from_str(text), None)
+    }
+}
+
+/// Reader that reports how many bytes have gone through it.
+struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    total: u64,
+    progress: F,
+}
+
+impl<R: Read, F: FnMut(u64, u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        (self.progress)(self.read, self.total);
+        Ok(read)
+    }
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map_or_else(|| "untitled".into(), |name| name.to_string_lossy());
+    path.with_file_name(format!(".{}.stte-tmp", file_name))
+}
+
+/** Copies the file at `path` to a backup before it's saved over. Numbered backups
+go one past the highest existing number, and the oldest are removed so only the
+newest ones are left. Does nothing if the file doesn't exist yet. */
+fn write_backup(path: &Path, backup: Backup) -> Result<(), BufferError> {
+    if backup == Backup::Off || !path.exists() {
+        return Ok(());
+    }
+    let file_name = path
+        .file_name()
+        .map_or_else(|| "untitled".into(), |name| name.to_string_lossy());
+    let backup_error = |error| BufferError {
+        message: "Can't write backup — changes NOT saved".to_string(),
+        cause: Some(error),
+    };
+    let kept = match backup {
+        Backup::Numbered(kept) => kept,
+        _ => {
+            let backup_path = path.with_file_name(format!("{}~", file_name));
+            return fs::copy(path, backup_path)
+                .map(|_| ())
+                .map_err(backup_error);
+        }
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.~", file_name);
+    let mut numbers: Vec<u32> = fs::read_dir(dir)
+        .map_err(backup_error)?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_prefix(&prefix)?.strip_suffix('~')?.parse().ok()
+        })
+        .collect();
+    numbers.sort_unstable();
+    let next = numbers.last().map_or(1, |last| last + 1);
+    let backup_path = |number| path.with_file_name(format!("{}{}~", prefix, number));
+    fs::copy(path, backup_path(next)).map_err(backup_error)?;
+    // The new copy counts towards the ones kept
+    let stale = (numbers.len() + 1).saturating_sub(kept);
+    for number in &numbers[..stale.min(numbers.len())] {
+        let _ = fs::remove_file(backup_path(*number));
+    }
+    Ok(())
+}
+
+fn save_error(error: io::Error) -> BufferError {
+    let message = match error.kind() {
+        ErrorKind::PermissionDenied => "Can't write to file",
+        ErrorKind::StorageFull => "Disk full — changes NOT saved",
+        _ => "Write failed — changes NOT saved",
+    };
+    BufferError {
+        message: message.to_string(),
+        cause: Some(error),
+    }
+}
+
+/// The ending of the first line, or `None` if there's only one line.
+fn detect_line_ending(text: &Rope) -> Option<LineEnding> {
+    let first_line = text.lines().next()?;
+    let len = first_line.len_chars();
+    if len == 0 || first_line.char(len - 1) != '\n' {
+        None
+    } else if len > 1 && first_line.char(len - 2) == '\r' {
+        Some(LineEnding::CRLF)
+    } else {
+        Some(LineEnding::LF)
+    }
+}
+
+/** Guesses the indentation style from the leading whitespace of the first
+non-blank lines. Returns `None` when there's too little indentation to tell. */
+fn detect_indent_style(text: &Rope) -> Option<IndentStyle> {
+    const SAMPLE_LINES: usize = 200;
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    // How often each change in space indentation between neighbouring lines occurs
+    let mut deltas = [0usize; 9];
+    let mut previous_spaces = 0;
+
+    for line in text
+        .lines()
+        .filter(|line| line.chars().any(|ch| !ch.is_whitespace()))
+        .take(SAMPLE_LINES)
+    {
+        match line.char(0) {
+            '\t' => {
+                tab_lines += 1;
+                continue;
+            }
+            ' ' => space_lines += 1,
+            _ => {}
+        }
+        let spaces = line.chars().take_while(|ch| *ch == ' ').count();
+        let delta = spaces.abs_diff(previous_spaces);
+        if (2..deltas.len()).contains(&delta) {
+            deltas[delta] += 1;
+        }
+        previous_spaces = spaces;
+    }
+
+    if tab_lines > space_lines {
+        return Some(IndentStyle::Tabs);
+    }
+    let (width, count) = deltas
+        .iter()
+        .enumerate()
+        .max_by_key(|(width, count)| (**count, *width))?;
+    if space_lines > tab_lines && *count > 0 {
+        Some(IndentStyle::Spaces(width))
+    } else {
+        None
+    }
+}
+
+/** Indentation of `line` in levels of `width` columns, with a tab counting as
+one level. Returns `None` for blank lines, which don't have an indentation of their own. */
+fn indent_level(line: RopeSlice, width: usize) -> Option<usize> {
+    let width = width.max(1);
+    let mut columns = 0;
+    for ch in line.chars() {
+        match ch {
+            '\t' => columns += width - columns % width,
+            ' ' => columns += 1,
+            _ if is_line_break(ch) => return None,
+            _ => return Some(columns / width),
+        }
+    }
+    None
+}
+
+/// The whitespace that starts `line`.
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Puts `token` in front of every non-blank line, lined up at the least indented of them.
+fn add_line_comments(text: &str, token: &str) -> String {
+    let indent = text
+        .split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indentation(line).len())
+        .min()
+        .unwrap_or(0);
+    text.split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.to_string()
+            } else {
+                format!("{}{} {}", &line[..indent], token, &line[indent..])
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/** Takes `token`, and a space after it, off every non-blank line. Returns `None`
+unless every one of them starts with it. */
+fn remove_line_comments(text: &str, token: &str) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut any = false;
+    for line in text.split('\n') {
+        if line.trim().is_empty() {
+            lines.push(line.to_string());
+            continue;
+        }
+        let indent = indentation(line);
+        let rest = line[indent.len()..].strip_prefix(token)?;
+        let rest = rest.strip_prefix(' ').unwrap_or(rest);
+        lines.push(format!("{}{}", indent, rest));
+        any = true;
+    }
+    any.then(|| lines.join("\n"))
+}
+
+/// Wraps `text` in a block comment, leaving the first line's indentation outside it.
+fn wrap_block_comment(text: &str, open: &str, close: &str) -> String {
+    let indent = indentation(text);
+    format!("{}{} {} {}", indent, open, &text[indent.len()..], close)
+}
+
+/** Removes a block comment that wraps the whole of `text`, along with the spaces
+just inside its delimiters. Returns `None` if `text` isn't wrapped in one. */
+fn unwrap_block_comment(text: &str, open: &str, close: &str) -> Option<String> {
+    let indent = indentation(text);
+    let inner = text[indent.len()..]
+        .trim_end()
+        .strip_prefix(open)?
+        .strip_suffix(close)
+        // Otherwise it's two comments with code between them
+        .filter(|inner| !inner.contains(close))?;
+    let inner = inner.strip_prefix(' ').unwrap_or(inner);
+    let inner = inner.strip_suffix(' ').unwrap_or(inner);
+    Some(format!("{}{}", indent, inner))
+}
+
+/// Columns taken up by indentation made of `chars`.
+fn indent_width(chars: impl Iterator<Item = char>, tab_width: usize) -> usize {
+    chars.fold(0, |columns, ch| match ch {
+        '\t' => columns + tab_width - columns % tab_width,
+        _ => columns + 1,
+    })
+}
+
+/** Shifts the indentation of every line of `text` so the first starts `target`
+columns in, keeping the others where they were relative to it. Blank lines lose
+their whitespace. */
+fn reindent(text: &str, target: usize, style: IndentStyle, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let leading = |line: &str| {
+        line.chars()
+            .take_while(|ch| matches!(ch, ' ' | '\t'))
+            .count()
+    };
+    let base = text.lines().next().map_or(0, |line| {
+        indent_width(line.chars().take(leading(line)), tab_width)
+    });
+    text.split_inclusive('\n')
+        .map(|line| {
+            let indent_len = leading(line);
+            // Char counts match byte counts, since spaces and tabs are ASCII
+            let rest = &line[indent_len..];
+            if rest.trim().is_empty() {
+                return rest
+                    .trim_start_matches(|ch: char| !is_line_break(ch))
+                    .to_string();
+            }
+            let width = indent_width(line.chars().take(indent_len), tab_width);
+            let width = (target + width).saturating_sub(base);
+            let indent = match style {
+                IndentStyle::Tabs => {
+                    "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+                }
+                IndentStyle::Spaces(_) => " ".repeat(width),
+            };
+            indent + rest
+        })
+        .collect()
+}
+
+/// Number of spaces and tabs at the start of `line`.
+fn leading_whitespace_len(line: RopeSlice) -> usize {
+    line.chars()
+        .take_while(|ch| *ch == ' ' || *ch == '\t')
+        .count()
+}
+
+/** How a control character is shown, so it can't act on the terminal: `^` and the
+character 64 away, like `^[` for escape and `^?` for delete. Tabs and line breaks
+aren't shown this way. */
+pub fn caret_notation(ch: char) -> Option<char> {
+    if !ch.is_ascii_control() || ch == '\t' || is_line_break(ch) {
+        return None;
+    }
+    Some(((ch as u8) ^ 0x40) as char)
+}
+
+/** Columns `ch` takes on screen when it starts at visual column `column`. The
+screen draws lines and the buffer places the cursor by this, so they always agree. */
+pub fn char_width(ch: char, column: usize, tab_stops: &TabStops) -> usize {
+    match ch {
+        '\t' => tab_stops.width_at(column),
+        _ if caret_notation(ch).is_some() => 2,
+        _ => ch.width().unwrap_or(1),
+    }
+}
+
+/// The characters ropey treats as ending a line.
+pub fn is_line_break(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+/** Which characters make up a word, like vim's `iskeyword`. Letters and digits always
+count, along with the extra characters given, so `-` can be part of a word in CSS
+or `$` in shell. Every word-based feature goes through this so they all agree. */
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordChars {
+    extra: Vec<char>,
+}
+
+impl WordChars {
+    pub fn new(extra: &str) -> Self {
+        Self {
+            extra: extra.chars().collect(),
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        c.is_alphanumeric() || self.extra.contains(&c)
+    }
+}
+
+/// The extra characters, as `WordChars::new` takes them.
+impl fmt::Display for WordChars {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.extra.iter().try_for_each(|c| write!(f, "{}", c))
+    }
+}
+
+impl Default for WordChars {
+    fn default() -> Self {
+        Self::new("_")
+    }
+}
+
+/// The kinds of characters word movement tells apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+/// Toggles that change how search queries match.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchOptions {
+    pub case_insensitive: bool,
+    pub whole_word: bool,
+}
+
+/** Finds every non-overlapping occurrence of `needle` in `line`, returning char column ranges.
+Case-insensitive matching compares char by char, so no lowercased copy of the line is made
+and the returned columns always line up with the original text. */
+pub fn find_matches(
+    line: &str,
+    needle: &str,
+    options: SearchOptions,
+    word_chars: &WordChars,
+) -> Vec<Range<usize>> {
+    let haystack: Vec<char> = line.chars().collect();
+    let needle: Vec<char> = needle.chars().collect();
+    let mut matches = Vec::new();
+    if needle.is_empty() {
+        return matches;
+    }
+    let mut start = 0;
+    while start + needle.len() <= haystack.len() {
+        let end = start + needle.len();
+        let found = haystack[start..end]
+            .iter()
+            .zip(&needle)
+            .all(|(a, b)| chars_match(*a, *b, options.case_insensitive));
+        let touches_word = start > 0 && word_chars.contains(haystack[start - 1])
+            || haystack.get(end).is_some_and(|ch| word_chars.contains(*ch));
+        if found && !(options.whole_word && touches_word) {
+            matches.push(start..end);
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+fn chars_match(a: char, b: char, case_insensitive: bool) -> bool {
+    a == b || case_insensitive && a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Makes `edit` as one undo step, then checks undo brings back the text from
+    before it and redo the text after it, twice over. */
+    fn assert_round_trip(text: &str, cursor: usize, edit: impl FnOnce(&mut Buffer)) -> String {
+        let mut buffer = Buffer::from(text);
+        buffer.set_cursor_pos(cursor);
+        edit(&mut buffer);
+        buffer.end_undo_step();
+        let edited = buffer.to_string();
+        assert_ne!(edited, text, "the edit changed nothing");
+        for _ in 0..2 {
+            assert_eq!(buffer.undo(), Some(()));
+            assert_eq!(buffer.to_string(), text);
+            assert!(matches!(buffer.status(), Status::Clean));
+            assert_eq!(buffer.undo(), None);
+            assert_eq!(buffer.redo(), Some(()));
+            assert_eq!(buffer.to_string(), edited);
+            assert!(matches!(buffer.status(), Status::Modified));
+            assert_eq!(buffer.redo(), None);
+        }
+        edited
+    }
+
+    #[test]
+    fn undo_typing() {
+        let edited = assert_round_trip("ab\n", 1, |buffer| {
+            buffer.insert_char('x');
+            buffer.insert_char('y');
+        });
+        assert_eq!(edited, "axyb\n");
+    }
+
+    #[test]
+    fn undo_delete() {
+        let edited = assert_round_trip("ab\ncd\n", 3, |buffer| {
+            buffer.delete_char().unwrap();
+        });
+        assert_eq!(edited, "abcd\n");
+    }
+
+    #[test]
+    fn undo_newline_indented_drops_trailing_whitespace() {
+        let edited = assert_round_trip("foo   bar\n", 3, |buffer| {
+            buffer
+                .insert_newline_indented(IndentStyle::Tabs, false)
+                .unwrap();
+        });
+        assert_eq!(edited, "foo\nbar\n");
+    }
+
+    #[test]
+    fn undo_newline_indented_between_brackets() {
+        let edited = assert_round_trip("  f() {  }\n", 7, |buffer| {
+            buffer
+                .insert_newline_indented(IndentStyle::Spaces(2), true)
+                .unwrap();
+        });
+        assert_eq!(edited, "  f() {\n    \n  }\n");
+    }
+
+    #[test]
+    fn undo_several_newlines_in_turn() {
+        let mut buffer = Buffer::from("a  b  c\n");
+        buffer.set_cursor_pos(1);
+        buffer
+            .insert_newline_indented(IndentStyle::Tabs, false)
+            .unwrap();
+        buffer.end_undo_step();
+        buffer.set_cursor_pos(3);
+        buffer
+            .insert_newline_indented(IndentStyle::Tabs, false)
+            .unwrap();
+        buffer.end_undo_step();
+        assert_eq!(buffer.to_string(), "a\nb\nc\n");
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "a\nb  c\n");
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "a  b  c\n");
+    }
+
+    #[test]
+    fn undo_replace_all() {
+        let edited = assert_round_trip("one two one\none\n", 0, |buffer| {
+            let count =
+                buffer.replace_all("one", "three", SearchOptions::default(), None, |_, _| {});
+            assert_eq!(count, 3);
+        });
+        assert_eq!(edited, "three two three\nthree\n");
+    }
+
+    #[test]
+    fn undo_replace_next() {
+        let edited = assert_round_trip("one two one\n", 1, |buffer| {
+            assert!(buffer.replace_next("one", "1", SearchOptions::default()));
+        });
+        assert_eq!(edited, "one two 1\n");
+    }
+
+    #[test]
+    fn undo_paste() {
+        let edited = assert_round_trip("ab\n", 1, |buffer| buffer.insert_str("x\ny"));
+        assert_eq!(edited, "ax\nyb\n");
+        let edited = assert_round_trip("ab\ncd\n", 0, |buffer| {
+            buffer.insert_lines_below("x\n");
+        });
+        assert_eq!(edited, "ab\nx\ncd\n");
+        let edited = assert_round_trip("    ab\n", 6, |buffer| {
+            buffer.insert_reindented("x\n  y\n", IndentStyle::Spaces(4));
+        });
+        assert_eq!(edited, "    ab\n    x\n      y\n");
+    }
+
+    #[test]
+    fn undo_delete_word() {
+        let edited = assert_round_trip("foo.bar()  \n", 11, |buffer| {
+            buffer.delete_word_backward().unwrap();
+        });
+        assert_eq!(edited, "foo.bar\n");
+        let edited = assert_round_trip("ab\ncd\n", 3, |buffer| {
+            buffer.delete_word_backward().unwrap();
+        });
+        assert_eq!(edited, "abcd\n");
+    }
+
+    #[test]
+    fn typing_undoes_word_by_word() {
+        let mut buffer = Buffer::from("");
+        for c in "one two".chars() {
+            buffer.insert_char(c);
+            buffer.end_undo_step();
+        }
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "one");
+        assert_eq!(buffer.cursor_pos(), 3);
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "");
+        assert_eq!(buffer.undo(), None);
+    }
+
+    #[test]
+    fn typing_somewhere_else_starts_a_new_step() {
+        let mut buffer = Buffer::from("one two");
+        buffer.set_cursor_pos(3);
+        buffer.insert_char('s');
+        buffer.end_undo_step();
+        buffer.set_cursor_pos(8);
+        buffer.insert_char('s');
+        buffer.end_undo_step();
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "ones two");
+        assert_eq!(buffer.cursor_pos(), 8);
+        buffer.undo();
+        assert_eq!(buffer.to_string(), "one two");
+        assert_eq!(buffer.cursor_pos(), 3);
+    }
+
+    #[test]
+    fn editing_after_undo_drops_redo() {
+        let mut buffer = Buffer::from("a");
+        buffer.set_cursor_pos(1);
+        buffer.insert_char('b');
+        buffer.end_undo_step();
+        buffer.undo();
+        buffer.insert_char('c');
+        buffer.end_undo_step();
+        assert_eq!(buffer.redo(), None);
+        assert_eq!(buffer.to_string(), "ac");
+    }
+
+    #[test]
+    fn saved_text_is_clean_again_after_undo_and_redo() {
+        let path = std::env::temp_dir().join(format!("stte-undo-test-{}", std::process::id()));
+        let mut buffer = Buffer::from("a");
+        buffer.set_file_path(path.clone());
+        buffer.set_cursor_pos(1);
+        buffer.insert_char('b');
+        buffer.end_undo_step();
+        buffer.save(Backup::Off).unwrap();
+        assert!(matches!(buffer.status(), Status::Clean));
+        buffer.undo();
+        assert!(matches!(buffer.status(), Status::Modified));
+        buffer.redo();
+        assert!(matches!(buffer.status(), Status::Clean));
+        // Once the saved text can't be reached by redo, nothing is clean until the next save
+        buffer.undo();
+        buffer.insert_char('c');
+        buffer.end_undo_step();
+        buffer.undo();
+        assert!(matches!(buffer.status(), Status::Modified));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn normalized_crlf_file_is_saved_as_crlf() {
+        let path = std::env::temp_dir().join(format!("stte-eol-test-{}", std::process::id()));
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        let mut buffer = Buffer::from_path(path.to_str().unwrap()).unwrap();
+        buffer.normalize_line_endings();
+        assert_eq!(buffer.to_string(), "one\ntwo\n");
+        buffer.move_to(0, 3);
+        buffer.insert_newline().unwrap();
+        buffer.insert_str("new");
+        assert_eq!(buffer.to_string(), "one\nnew\ntwo\n");
+        let message = buffer.save(Backup::Off).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\r\nnew\r\ntwo\r\n");
+        assert!(message.starts_with("Wrote 15 bytes to "), "{}", message);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Selects from char `anchor` to char `cursor`.
+    fn select(buffer: &mut Buffer, anchor: usize, cursor: usize) {
+        buffer.clear_selection();
+        buffer.set_cursor_pos(anchor);
+        buffer.start_selection();
+        buffer.set_cursor_pos(cursor);
+    }
+
+    #[test]
+    fn selected_lines_cover_the_selection() {
+        let mut buffer = Buffer::from("a\nb\nc\nd\n");
+        buffer.set_cursor_pos(2);
+        assert_eq!(buffer.selected_lines(), 1..2);
+        select(&mut buffer, 5, 1);
+        assert_eq!(buffer.selected_lines(), 0..3);
+        // Ending at the start of a line leaves that line out
+        select(&mut buffer, 2, 6);
+        assert_eq!(buffer.selected_lines(), 1..3);
+    }
+
+    #[test]
+    fn toggle_comment_on_selected_lines() {
+        let syntax = CommentSyntax {
+            line: Some("//"),
+            block: Some(("/*", "*/")),
+        };
+        let mut buffer = Buffer::from("a\nb\nc\n");
+        select(&mut buffer, 0, 3);
+        let lines = buffer.selected_lines();
+        assert!(buffer.toggle_comment(lines, syntax, false));
+        assert_eq!(buffer.to_string(), "// a\n// b\nc\n");
+    }
+
+    #[test]
+    fn duplicate_selected_lines() {
+        let mut buffer = Buffer::from("a\nb\nc\n");
+        select(&mut buffer, 2, 5);
+        let lines = buffer.selected_lines();
+        buffer.duplicate_lines(lines);
+        assert_eq!(buffer.to_string(), "a\nb\nc\nb\nc\n");
+    }
+
+    #[test]
+    fn revert_is_one_undoable_step() {
+        let mut buffer = Buffer::from("one\ntwo\n");
+        buffer.move_to(1, 0);
+        buffer.insert_str("new ");
+        buffer.end_undo_step();
+        buffer.revert_to(Buffer::from("one\ntwo\n"));
+        assert_eq!(buffer.to_string(), "one\ntwo\n");
+        assert!(matches!(buffer.status(), Status::Clean));
+        assert_eq!(buffer.get_cursor_xy(), (3, 1));
+        assert_eq!(buffer.undo(), Some(()));
+        assert_eq!(buffer.to_string(), "one\nnew two\n");
+        assert!(matches!(buffer.status(), Status::Modified));
+        assert_eq!(buffer.redo(), Some(()));
+        assert!(matches!(buffer.status(), Status::Clean));
+    }
+
+    #[test]
+    fn save_errors_say_the_changes_are_not_saved() {
+        let message = |kind| save_error(io::Error::from(kind)).to_string();
+        assert!(message(ErrorKind::StorageFull).starts_with("Disk full — changes NOT saved: "));
+        assert!(message(ErrorKind::PermissionDenied).starts_with("Can't write to file: "));
+        assert!(message(ErrorKind::Interrupted).starts_with("Write failed — changes NOT saved: "));
+        let error = save_error(io::Error::from(ErrorKind::StorageFull));
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn failed_save_leaves_the_original_file() {
+        let dir = std::env::temp_dir().join(format!("stte-save-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "original\n").unwrap();
+        // A directory where the temporary copy would go makes the write fail
+        fs::create_dir_all(temp_path_for(&path)).unwrap();
+        let mut buffer = Buffer::from_path(path.to_str().unwrap()).unwrap();
+        buffer.insert_str("changed ");
+        let error = buffer.save(Backup::Off).unwrap_err();
+        assert!(error.to_string().contains("changes NOT saved"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original\n");
+        assert!(matches!(buffer.status(), Status::Modified));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cursor_never_lands_inside_crlf() {
+        let mut buffer = Buffer::from("a\r\nb");
+        buffer.move_cursor_line_end();
+        assert_eq!(buffer.cursor_pos(), 1);
+        assert!(!buffer.move_cursor_right());
+        assert!(buffer.move_to_next_line_start());
+        assert_eq!(buffer.cursor_pos(), 3);
+        assert!(!buffer.move_cursor_left());
+        assert!(buffer.move_to_previous_line_end());
+        assert_eq!(buffer.cursor_pos(), 1);
+        buffer.move_cursor_down();
+        assert_eq!(buffer.cursor_pos(), 4);
+        buffer.move_cursor_up();
+        assert_eq!(buffer.cursor_pos(), 1);
+        buffer.move_cursor_line_start();
+        assert_eq!(buffer.cursor_pos(), 0);
+    }
+
+    #[test]
+    fn failed_revert_leaves_the_buffer() {
+        let dir = std::env::temp_dir().join(format!("stte-revert-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut buffer = Buffer::from("edited\n");
+        buffer.insert_str("more ");
+        // A directory opens, but can't be read as a file
+        buffer.set_file_path(dir.clone());
+        let result = buffer.revert(|path| Buffer::from_path(path.to_str().unwrap()));
+        assert!(result.is_err());
+        assert_eq!(buffer.to_string(), "more edited\n");
+        assert!(matches!(buffer.status(), Status::Modified));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Types `text` a key at a time, the way a terminal without bracketed paste sends a paste.
+    fn type_keys(buffer: &mut Buffer, text: &str, newline: fn(&mut Buffer)) {
+        for c in text.chars() {
+            if c == '\n' {
+                newline(buffer);
+            } else {
+                buffer.insert_char(c);
+            }
+        }
+    }
+
+    #[test]
+    fn pasted_indentation_is_not_doubled() {
+        let block = "if x {\n    y();\n}\n";
+        let mut buffer = Buffer::from("");
+        buffer.insert_str(block);
+        assert_eq!(buffer.to_string(), block);
+        // Newlines arriving with more input waiting go in without auto-indent
+        let mut buffer = Buffer::from("");
+        type_keys(&mut buffer, block, |buffer| {
+            buffer.insert_newline().unwrap()
+        });
+        assert_eq!(buffer.to_string(), block);
+        // Auto-indent on every newline would indent the pasted lines a second time
+        let mut buffer = Buffer::from("");
+        type_keys(&mut buffer, block, |buffer| {
+            buffer
+                .insert_newline_indented(IndentStyle::Spaces(4), false)
+                .unwrap()
+        });
+        assert_ne!(buffer.to_string(), block);
+    }
+
+    #[test]
+    fn line_counts_with_and_without_a_final_newline() {
+        let mut buffer = Buffer::from("a\nb");
+        assert_eq!((buffer.line_count(), buffer.shown_line_count()), (2, 2));
+        buffer.set_cursor_pos(3);
+        assert_eq!((buffer.line_count(), buffer.shown_line_count()), (2, 2));
+        assert!(!buffer.move_cursor_down());
+
+        let mut buffer = Buffer::from("a\nb\n");
+        assert_eq!((buffer.line_count(), buffer.shown_line_count()), (2, 2));
+        buffer.set_cursor_pos(2);
+        assert!(!buffer.move_cursor_down());
+        // Past the final line break, as right after Enter at the end
+        buffer.set_cursor_pos(4);
+        assert_eq!((buffer.line_count(), buffer.shown_line_count()), (2, 3));
+
+        let buffer = Buffer::from("");
+        assert_eq!((buffer.line_count(), buffer.shown_line_count()), (1, 1));
+    }
+
+    #[test]
+    fn folds_hide_lines_without_changing_the_counts() {
+        let mut buffer = Buffer::from("f\n  x\n  y\ng\n");
+        assert_eq!(buffer.fold_to_level(0, 2), 1);
+        assert_eq!(buffer.fold_at(0), Some(&(0..3)));
+        assert_eq!((buffer.line_count(), buffer.shown_line_count()), (4, 4));
+        assert_eq!(buffer.visible_lines_between(0, buffer.line_count()), 2);
+        buffer.set_cursor_pos(buffer.len_chars());
+        assert_eq!((buffer.line_count(), buffer.shown_line_count()), (4, 5));
+        assert_eq!(
+            buffer.visible_lines_between(0, buffer.shown_line_count()),
+            3
+        );
+    }
+
+    #[test]
+    fn rewriting_the_file_counts_as_changed_on_disk() {
+        let path = std::env::temp_dir().join(format!("stte-disk-test-{}", std::process::id()));
+        fs::write(&path, "one\n").unwrap();
+        let mut buffer = Buffer::from_path(path.to_str().unwrap()).unwrap();
+        assert!(!buffer.changed_on_disk());
+        buffer.insert_str("new ");
+        buffer.save(Backup::Off).unwrap();
+        assert!(!buffer.changed_on_disk());
+        // Another program writes the file, which ends up with a different time
+        fs::write(&path, "changed elsewhere\n").unwrap();
+        let earlier = SystemTime::now() - std::time::Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+        assert!(buffer.changed_on_disk());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn line_ranges_at_the_edges() {
+        let buffer = Buffer::from("a\nb\nc");
+        let lines = |range: Range<usize>| -> Vec<String> {
+            buffer
+                .lines_in_range(range)
+                .map(|line| line.to_string())
+                .collect()
+        };
+        // The start line is included and the end line isn't
+        assert_eq!(lines(0..1), ["a\n"]);
+        assert_eq!(buffer.line_range_chars(0, 1), 0..2);
+        assert!(lines(1..1).is_empty());
+        assert_eq!(buffer.line_range_chars(1, 1), 2..2);
+        // The last line has no line ending to include
+        assert_eq!(lines(2..3), ["c"]);
+        assert_eq!(buffer.line_range_chars(2, 3), 4..5);
+        // Ranges running past the end stop at it
+        assert_eq!(lines(1..10), ["b\n", "c"]);
+        assert_eq!(buffer.line_range_chars(1, 10), 2..5);
+        assert!(lines(7..9).is_empty());
+        assert_eq!(buffer.line_range_chars(7, 9), 5..5);
+    }
+
+    #[test]
+    fn oldest_undo_steps_are_dropped_past_the_limit() {
+        let mut buffer = Buffer::from("");
+        buffer.set_undo_levels(2);
+        // Each word is one step, however many characters it took
+        for c in "one two three".chars() {
+            buffer.insert_char(c);
+            buffer.end_undo_step();
+        }
+        assert_eq!(buffer.undo(), Some(()));
+        assert_eq!(buffer.undo(), Some(()));
+        assert_eq!(buffer.to_string(), "one");
+        assert_eq!(buffer.undo(), None);
+        assert_eq!(buffer.redo(), Some(()));
+        assert_eq!(buffer.redo(), Some(()));
+        assert_eq!(buffer.to_string(), "one two three");
+    }
+
+    #[test]
+    fn saved_text_is_forgotten_with_the_steps_leading_to_it() {
+        let mut buffer = Buffer::from("");
+        for c in "a b".chars() {
+            buffer.insert_char(c);
+            buffer.end_undo_step();
+        }
+        assert!(matches!(buffer.status(), Status::Modified));
+        // Lowering the limit drops the step back to the text as it was loaded
+        buffer.set_undo_levels(1);
+        assert_eq!(buffer.undo(), Some(()));
+        assert_eq!(buffer.to_string(), "a");
+        assert!(matches!(buffer.status(), Status::Modified));
+        assert_eq!(buffer.undo(), None);
+    }
+
+    #[test]
+    fn keep_only_the_selection() {
+        let mut buffer = Buffer::from("one\ntwo\n");
+        assert!(!buffer.keep_selection());
+        let edited = assert_round_trip("one\ntwo\nthree\n", 0, |buffer| {
+            select(buffer, 5, 9);
+            assert!(buffer.keep_selection());
+            assert_eq!(buffer.cursor_pos(), 0);
+        });
+        assert_eq!(edited, "wo\nt");
+    }
 }
@@ -0,0 +1,72 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/** A build command running in the background. Its stdout and stderr are collected
+line by line, interleaved in the order the lines arrive. */
+pub struct Build {
+    child: Child,
+    lines: Receiver<String>,
+    output: String,
+}
+
+impl Build {
+    /// Starts `command` with the shell, in `dir`.
+    pub fn spawn(command: &str, dir: &Path) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (sender, lines) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            forward_lines(stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward_lines(stderr, sender);
+        }
+        Ok(Self {
+            child,
+            lines,
+            output: String::new(),
+        })
+    }
+
+    /** Collects the output so far and checks whether the command has exited. Once it
+    has, returns its exit status along with everything it printed. */
+    pub fn try_finish(&mut self) -> io::Result<Option<(ExitStatus, &str)>> {
+        self.output
+            .extend(self.lines.try_iter().map(|line| line + "\n"));
+        let Some(status) = self.child.try_wait()? else {
+            return Ok(None);
+        };
+        // Waits for the readers to hit the end of the pipes
+        self.output
+            .extend(self.lines.iter().map(|line| line + "\n"));
+        Ok(Some((status, &self.output)))
+    }
+
+    /// Stops the command. Anything it started itself may keep running.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.child.kill()?;
+        self.child.wait().map(|_| ())
+    }
+}
+
+fn forward_lines(pipe: impl Read + Send + 'static, sender: Sender<String>) {
+    thread::spawn(move || {
+        for line in BufReader::new(pipe).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+}
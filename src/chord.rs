@@ -0,0 +1,59 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/** Key sequences typed after Ctrl+K and the command each one runs, as it would be
+typed at the `:` prompt. Keys are separated by spaces, with `C-` for Ctrl. */
+pub const CHORDS: &[(&str, &str)] = &[
+    ("C-c", "comment"),
+    ("b", "build"),
+    ("d", "duplicate"),
+    ("k", "keep"),
+    ("q l", "clist"),
+    ("q n", "cnext"),
+    ("q p", "cprev"),
+    ("r", "registers"),
+    ("s", "strip"),
+    ("z", "zen"),
+];
+
+/// What the keys typed so far amount to.
+#[derive(Debug, PartialEq)]
+pub enum Resolution {
+    /// A whole sequence, so its command should run.
+    Command(&'static str),
+    /// The start of one or more longer sequences, so more keys are needed.
+    Pending,
+    /// Nothing starts with these keys.
+    Unbound,
+}
+
+/** The name a key goes by in `CHORDS`: the character, with `C-` in front when Ctrl
+is held. Keys without a character, like arrows, have no name. */
+pub fn key_name(key: &KeyEvent) -> Option<String> {
+    let KeyCode::Char(ch) = key.code else {
+        return None;
+    };
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        Some(format!("C-{}", ch.to_ascii_lowercase()))
+    } else {
+        Some(ch.to_string())
+    }
+}
+
+/// Looks up the keys typed after the prefix, as named by `key_name`.
+pub fn resolve(keys: &[String]) -> Resolution {
+    let typed = keys.join(" ");
+    let mut pending = false;
+    for (sequence, command) in CHORDS {
+        if *sequence == typed {
+            return Resolution::Command(command);
+        }
+        pending |= sequence
+            .strip_prefix(typed.as_str())
+            .is_some_and(|rest| rest.starts_with(' '));
+    }
+    if pending {
+        Resolution::Pending
+    } else {
+        Resolution::Unbound
+    }
+}
@@ -0,0 +1,188 @@
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/** The operating system's clipboard. Built with the `clipboard` feature it's reached
+through arboard, and otherwise, or when arboard can't connect, through whichever
+command line tool the platform has for it, such as `wl-copy` or `xclip`. */
+pub enum SystemClipboard {
+    #[cfg(feature = "clipboard")]
+    Native(arboard::Clipboard),
+    Tool(ClipboardTool),
+}
+
+/// A command line tool to copy to the clipboard with, and its counterpart to paste.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipboardTool {
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+/// Tools to try, in order, with the environment variable that has to be set for each.
+const TOOLS: &[(Option<&str>, ClipboardTool)] = &[
+    (
+        Some("WAYLAND_DISPLAY"),
+        ClipboardTool {
+            copy: &["wl-copy"],
+            paste: &["wl-paste", "--no-newline"],
+        },
+    ),
+    (
+        Some("DISPLAY"),
+        ClipboardTool {
+            copy: &["xclip", "-selection", "clipboard"],
+            paste: &["xclip", "-selection", "clipboard", "-o"],
+        },
+    ),
+    (
+        Some("DISPLAY"),
+        ClipboardTool {
+            copy: &["xsel", "--clipboard", "--input"],
+            paste: &["xsel", "--clipboard", "--output"],
+        },
+    ),
+    (
+        None,
+        ClipboardTool {
+            copy: &["pbcopy"],
+            paste: &["pbpaste"],
+        },
+    ),
+];
+
+impl SystemClipboard {
+    /** Connects to the clipboard, or finds a clipboard tool that's installed and
+    usable here. Returns `None` when there's no clipboard to reach, e.g. over SSH
+    without a display. */
+    pub fn detect() -> Option<Self> {
+        #[cfg(feature = "clipboard")]
+        if let Ok(clipboard) = arboard::Clipboard::new() {
+            return Some(SystemClipboard::Native(clipboard));
+        }
+        find_tool(
+            |var| env::var_os(var).is_some_and(|value| !value.is_empty()),
+            on_path,
+        )
+        .map(SystemClipboard::Tool)
+    }
+
+    pub fn copy(&mut self, text: &str) -> io::Result<()> {
+        match self {
+            #[cfg(feature = "clipboard")]
+            SystemClipboard::Native(clipboard) => {
+                clipboard.set_text(text).map_err(io::Error::other)
+            }
+            SystemClipboard::Tool(tool) => tool.copy(text),
+        }
+    }
+
+    pub fn paste(&mut self) -> io::Result<String> {
+        match self {
+            #[cfg(feature = "clipboard")]
+            SystemClipboard::Native(clipboard) => clipboard.get_text().map_err(io::Error::other),
+            SystemClipboard::Tool(tool) => tool.paste(),
+        }
+    }
+}
+
+/** The first tool in `TOOLS` whose environment variable `is_set` and whose copy
+and paste programs are both `installed`. */
+fn find_tool(
+    is_set: impl Fn(&str) -> bool,
+    installed: impl Fn(&str) -> bool,
+) -> Option<ClipboardTool> {
+    TOOLS
+        .iter()
+        .find(|(needs, tool)| {
+            needs.is_none_or(&is_set) && installed(tool.copy[0]) && installed(tool.paste[0])
+        })
+        .map(|(_, tool)| *tool)
+}
+
+impl ClipboardTool {
+    pub fn copy(&self, text: &str) -> io::Result<()> {
+        let mut child = Command::new(self.copy[0])
+            .args(&self.copy[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("{} failed", self.copy[0])));
+        }
+        Ok(())
+    }
+
+    pub fn paste(&self) -> io::Result<String> {
+        let output = Command::new(self.paste[0])
+            .args(&self.paste[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!("{} failed", self.paste[0])));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "clipboard isn't text"))
+    }
+}
+
+/// Whether an executable called `program` is in one of the `PATH` directories.
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| is_executable(&dir.join(program))))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The copy program of the tool picked with `vars` set and `programs` installed.
+    fn pick(vars: &[&str], programs: &[&str]) -> Option<&'static str> {
+        find_tool(
+            |var| vars.contains(&var),
+            |program| programs.contains(&program),
+        )
+        .map(|tool| tool.copy[0])
+    }
+
+    #[test]
+    fn no_tool_without_one_installed() {
+        assert_eq!(pick(&["WAYLAND_DISPLAY", "DISPLAY"], &[]), None);
+        // Half of a pair isn't enough
+        assert_eq!(pick(&["WAYLAND_DISPLAY"], &["wl-copy"]), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_next_tool_installed() {
+        let all = ["wl-copy", "wl-paste", "xclip", "xsel", "pbcopy", "pbpaste"];
+        assert_eq!(pick(&["WAYLAND_DISPLAY", "DISPLAY"], &all), Some("wl-copy"));
+        assert_eq!(
+            pick(
+                &["WAYLAND_DISPLAY", "DISPLAY"],
+                &["xsel", "pbcopy", "pbpaste"]
+            ),
+            Some("xsel")
+        );
+        // X11 tools need a display to talk to
+        assert_eq!(pick(&[], &all), Some("pbcopy"));
+        assert_eq!(pick(&[], &["xclip", "xsel"]), None);
+    }
+}
@@ -0,0 +1,780 @@
+use crate::buffer::{Backup, IndentStyle, TabStops, WordChars};
+use crossterm::cursor::SetCursorStyle;
+use crossterm::style::Color;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use unicode_width::UnicodeWidthChar;
+
+/// Shape of the terminal cursor while editing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+impl CursorShape {
+    /// Reads `block`, `bar` or `underline`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "block" => Some(CursorShape::Block),
+            "bar" => Some(CursorShape::Bar),
+            "underline" => Some(CursorShape::Underline),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CursorShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorShape::Block => write!(f, "block"),
+            CursorShape::Bar => write!(f, "bar"),
+            CursorShape::Underline => write!(f, "underline"),
+        }
+    }
+}
+
+/// How the file's path is shown in the status bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathDisplay {
+    /// Only the file name.
+    Name,
+    /// Relative to the working directory, or absolute for files outside it.
+    Relative,
+    /// Relative to the project root the file is in.
+    Project,
+    Absolute,
+}
+
+impl PathDisplay {
+    /// Reads `name`, `relative`, `project` or `absolute`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(PathDisplay::Name),
+            "relative" => Some(PathDisplay::Relative),
+            "project" => Some(PathDisplay::Project),
+            "absolute" => Some(PathDisplay::Absolute),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PathDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathDisplay::Name => write!(f, "name"),
+            PathDisplay::Relative => write!(f, "relative"),
+            PathDisplay::Project => write!(f, "project"),
+            PathDisplay::Absolute => write!(f, "absolute"),
+        }
+    }
+}
+
+/// Colors used when drawing the screen.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Background of other occurrences of the word under the cursor.
+    pub word_highlight: Color,
+    /// Background of search matches.
+    pub search_match: Color,
+    /// Background of the match the cursor is on.
+    pub current_match: Color,
+    pub scrollbar_thumb: Color,
+    pub scrollbar_track: Color,
+    /// Background of the minimap rows covering the visible lines.
+    pub minimap_viewport: Color,
+    /// Background of spaces and tabs at the end of a line.
+    pub trailing_whitespace: Color,
+    /// Color of the markers at the edges of lines too long to fit.
+    pub clip_marker: Color,
+    /// Color of the marker in front of the rows a wrapped line continues on.
+    pub wrap_marker: Color,
+    /// Text color of control characters, which are shown like `^G`.
+    pub control_char: Color,
+    /// Text color of status messages, by severity.
+    pub message_info: Color,
+    pub message_warning: Color,
+    pub message_error: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            word_highlight: Color::DarkGrey,
+            search_match: Color::DarkYellow,
+            current_match: Color::Yellow,
+            scrollbar_thumb: Color::Grey,
+            scrollbar_track: Color::Reset,
+            minimap_viewport: Color::DarkGrey,
+            trailing_whitespace: Color::Red,
+            clip_marker: Color::DarkGrey,
+            wrap_marker: Color::DarkGrey,
+            control_char: Color::Magenta,
+            message_info: Color::Reset,
+            message_warning: Color::Yellow,
+            message_error: Color::Red,
+        }
+    }
+}
+
+/// An on/off option `set` can change, and the `Config` field it controls.
+pub struct BoolOption {
+    pub name: &'static str,
+    get: fn(&Config) -> &bool,
+    get_mut: fn(&mut Config) -> &mut bool,
+}
+
+/// A setting with a value, written `name=value`, and the `Config` field it controls.
+pub struct ValueOption {
+    pub name: &'static str,
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> Result<(), String>,
+}
+
+/// Builds the `BoolOption` table from `"name" => field` pairs.
+macro_rules! bool_options {
+    ($($name:literal => $field:ident,)*) => {
+        &[$(BoolOption {
+            name: $name,
+            get: |config| &config.$field,
+            get_mut: |config| &mut config.$field,
+        },)*]
+    };
+}
+
+/** Editor-wide settings. Every value starts out from `Default`, and the options
+in `Config::OPTIONS` and `Config::VALUE_OPTIONS` can be changed by the config file
+at `config_path`. */
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub cursor_shape: CursorShape,
+    pub cursor_blink: bool,
+    /// Character shown in the first cell of an expanded tab, e.g. `»` or `│`.
+    pub tab_fill: char,
+    /// Whether counting the word under the cursor matches whole words only or any substring.
+    pub count_whole_word: bool,
+    /// Highlight every visible occurrence of the word under the cursor.
+    pub highlight_cursor_word: bool,
+    /** Highlight spaces and tabs at the end of lines, except on the cursor's line
+    so typing doesn't make it flicker. */
+    pub highlight_trailing_whitespace: bool,
+    /// Show a scrollbar on the right edge of the screen.
+    pub scrollbar: bool,
+    /// Show a condensed overview of the buffer next to the scrollbar.
+    pub minimap: bool,
+    /// Columns the minimap takes, though never more than half the screen.
+    pub minimap_width: usize,
+    /** Mark the edges where lines are cut off, with `›` when they go on past the
+    right edge and `‹` on the left when the view is scrolled sideways. */
+    pub clip_markers: bool,
+    /** Wrap lines wider than the screen onto the rows below, instead of scrolling
+    sideways to follow the cursor. */
+    pub wrap: bool,
+    /// Start the rows a wrapped line continues on with `↳`.
+    pub wrap_markers: bool,
+    /// Let Left and Right move across line boundaries.
+    pub wrap_cursor: bool,
+    /// Indent with spaces instead of tabs, unless the file's own style is detected.
+    pub expand_tab: bool,
+    /// Number of spaces per indentation level when `expand_tab` is set.
+    pub indent_width: usize,
+    /** Columns tabs stop at, for files laid out in tables. `None` keeps stops
+    every tab width. */
+    pub tab_stops: Option<TabStops>,
+    /** Typing replaces the character under the cursor instead of pushing it right.
+    Toggled with Insert. Backspace still deletes as usual. */
+    pub overwrite: bool,
+    /** Typing `</` in HTML or XML finishes the tag that's still open. Off by default,
+    so nothing is typed that wasn't asked for. */
+    pub close_tags: bool,
+    /// Comment out with block comments even in languages that have line comments.
+    pub block_comments: bool,
+    /** Adjust the indentation of text pasted into the terminal to where it lands.
+    Off by default, so pastes come out exactly as copied. */
+    pub reindent_paste: bool,
+    /// Start new lines with the indentation of the line above.
+    pub auto_indent: bool,
+    /** On top of `auto_indent`, indent one level deeper after an opening bracket and
+    dedent a closing bracket typed at the start of a line. Only applies to files
+    with one of the `smart_indent_extensions`, so prose isn't affected. */
+    pub smart_indent: bool,
+    pub smart_indent_extensions: Vec<String>,
+    /** How `{file}` shows the path in the status bar. Long paths lose directories
+    from the middle to fit, but the file name is always shown whole. */
+    pub path_display: PathDisplay,
+    /** Layout of the status bar. `{file}`, `{row}`, `{col}`, `{byte}` (the cursor's
+    byte offset), `{char}` (the character under the cursor and its code point)
+    and `{indent}` are replaced with their current values. */
+    pub status_format: String,
+    /** Show the cursor's byte offset after the rest of the status bar, for formats
+    that don't already have a `{byte}`. */
+    pub show_byte_offset: bool,
+    /** Convert CRLF line endings to LF when a file is loaded, so editing only ever
+    deals with LF. Files are still saved with the ending they were loaded with.
+    Changing it with `set` only affects files opened afterwards. */
+    pub normalize_line_endings: bool,
+    /** How long a status message stays on screen, set in seconds with `messagetimeout`.
+    Errors stay until the next keypress. */
+    pub status_message_timeout: Duration,
+    /// Apply the `.editorconfig` properties that match a file when it's opened.
+    pub editorconfig: bool,
+    /** Let a `stte:` comment near the top or bottom of a file override the tab width
+    and indentation for that file. Off by default, since it lets files change settings. */
+    pub modelines: bool,
+    /// Characters besides letters and digits that count as part of a word.
+    pub word_chars: WordChars,
+    /** Most undo steps kept for a buffer. A run of typing counts as one step, and
+    the oldest steps are dropped once there are more. */
+    pub undo_levels: usize,
+    /** Copy the file on disk aside before saving over it, so a bad save can be
+    undone by hand. Off by default. */
+    pub backup: Backup,
+    /** Shell command run by `build`, from the project root. Its output is read for
+    `file:line:col: message` lines, hence the short message format for cargo. */
+    pub build_command: String,
+    pub theme: Theme,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            cursor_shape: CursorShape::Bar,
+            cursor_blink: false,
+            tab_fill: ' ',
+            count_whole_word: true,
+            highlight_cursor_word: false,
+            highlight_trailing_whitespace: false,
+            scrollbar: false,
+            minimap: false,
+            minimap_width: 10,
+            clip_markers: true,
+            wrap: false,
+            wrap_markers: true,
+            wrap_cursor: true,
+            expand_tab: false,
+            indent_width: 4,
+            tab_stops: None,
+            overwrite: false,
+            block_comments: false,
+            close_tags: false,
+            reindent_paste: false,
+            auto_indent: true,
+            smart_indent: true,
+            smart_indent_extensions: [
+                "c", "cc", "cpp", "cs", "css", "go", "h", "hpp", "java", "js", "json", "jsx", "kt",
+                "php", "rs", "scala", "swift", "ts", "tsx", "zig",
+            ]
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect(),
+            path_display: PathDisplay::Relative,
+            status_format: "{file} - {row}:{col} - {indent}".to_string(),
+            show_byte_offset: false,
+            normalize_line_endings: false,
+            status_message_timeout: Duration::from_secs(3),
+            editorconfig: true,
+            modelines: false,
+            word_chars: WordChars::default(),
+            undo_levels: 1000,
+            backup: Backup::Off,
+            build_command: "cargo build --message-format=short".to_string(),
+            theme: Theme::default(),
+        }
+    }
+}
+
+/// The editor's config directory: `$XDG_CONFIG_HOME/stte`, or `~/.config/stte`.
+fn config_dir() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("stte"))
+}
+
+/// Where the config file lives: `$XDG_CONFIG_HOME/stte/config`, or `~/.config/stte/config`.
+pub fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config"))
+}
+
+/** The text new files with `path`'s extension start out with, read from
+`templates/<extension>` in the config directory, e.g. `templates/rs`. */
+pub fn template_for(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?;
+    fs::read_to_string(config_dir()?.join("templates").join(extension)).ok()
+}
+
+impl Config {
+    /** Reads a config file of options written as `set` takes them over the defaults.
+    On/off options can go any number to a line, while a `name = value` setting takes
+    a line of its own, since the value may have spaces in it. Lines starting with `#`
+    are comments. Options that can't be applied are skipped, and an error for each is
+    returned alongside the config. */
+    pub fn from_file(path: &Path) -> io::Result<(Config, Vec<String>)> {
+        let contents = fs::read_to_string(path)?;
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let settings: Vec<&str> = if line.contains('=') {
+                vec![line]
+            } else {
+                line.split_whitespace().collect()
+            };
+            for setting in settings {
+                if let Err(e) = config.set(setting) {
+                    errors.push(format!("{}:{}: {}", path.display(), idx + 1, e));
+                }
+            }
+        }
+        Ok((config, errors))
+    }
+
+    pub fn cursor_style(&self) -> SetCursorStyle {
+        match (self.cursor_shape, self.cursor_blink) {
+            (CursorShape::Block, false) => SetCursorStyle::SteadyBlock,
+            (CursorShape::Block, true) => SetCursorStyle::BlinkingBlock,
+            (CursorShape::Bar, false) => SetCursorStyle::SteadyBar,
+            (CursorShape::Bar, true) => SetCursorStyle::BlinkingBar,
+            (CursorShape::Underline, false) => SetCursorStyle::SteadyUnderScore,
+            (CursorShape::Underline, true) => SetCursorStyle::BlinkingUnderScore,
+        }
+    }
+
+    /** The tab fill character, falling back to a space when the configured
+    one isn't exactly one column wide, since a tab must still end on its tab stop. */
+    pub fn tab_fill(&self) -> char {
+        if self.tab_fill.width() == Some(1) {
+            self.tab_fill
+        } else {
+            ' '
+        }
+    }
+
+    pub fn indent_style(&self) -> IndentStyle {
+        if self.expand_tab {
+            IndentStyle::Spaces(self.indent_width)
+        } else {
+            IndentStyle::Tabs
+        }
+    }
+
+    /// Whether smart indentation applies to the file at `path`, going by its extension.
+    pub fn smart_indents(&self, path: Option<&Path>) -> bool {
+        self.smart_indent
+            && path
+                .and_then(|path| path.extension())
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    self.smart_indent_extensions
+                        .iter()
+                        .any(|known| known.eq_ignore_ascii_case(ext))
+                })
+    }
+
+    /// The on/off options that can be changed at runtime with `set`, by name.
+    pub const OPTIONS: &'static [BoolOption] = bool_options! {
+        "autoindent" => auto_indent,
+        "blockcomments" => block_comments,
+        "byteoffset" => show_byte_offset,
+        "clipmarkers" => clip_markers,
+        "closetags" => close_tags,
+        "cursorblink" => cursor_blink,
+        "expandtab" => expand_tab,
+        "minimap" => minimap,
+        "modelines" => modelines,
+        "normalizeeol" => normalize_line_endings,
+        "overwrite" => overwrite,
+        "reindentpaste" => reindent_paste,
+        "scrollbar" => scrollbar,
+        "smartindent" => smart_indent,
+        "trailing" => highlight_trailing_whitespace,
+        "wholeword" => count_whole_word,
+        "wordhighlight" => highlight_cursor_word,
+        "wrap" => wrap,
+        "wrapcursor" => wrap_cursor,
+        "wrapmarkers" => wrap_markers,
+    };
+
+    /// The options with values that can be changed at runtime with `set name=value`.
+    pub const VALUE_OPTIONS: &'static [ValueOption] = &[
+        ValueOption {
+            name: "buildcommand",
+            get: |config| config.build_command.clone(),
+            set: |config, value| {
+                config.build_command = value.to_string();
+                Ok(())
+            },
+        },
+        ValueOption {
+            name: "cursorshape",
+            get: |config| config.cursor_shape.to_string(),
+            set: |config, value| {
+                config.cursor_shape = CursorShape::parse(value).ok_or_else(|| {
+                    format!("expected block, bar or underline, not \"{}\"", value)
+                })?;
+                Ok(())
+            },
+        },
+        ValueOption {
+            name: "indentwidth",
+            get: |config| config.indent_width.to_string(),
+            set: |config, value| {
+                config.indent_width = parse_count(value)?;
+                Ok(())
+            },
+        },
+        // In seconds, which can have a fraction
+        ValueOption {
+            name: "messagetimeout",
+            get: |config| config.status_message_timeout.as_secs_f64().to_string(),
+            set: |config, value| {
+                config.status_message_timeout = value
+                    .parse()
+                    .ok()
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                    .ok_or_else(|| format!("expected a number of seconds, not \"{}\"", value))?;
+                Ok(())
+            },
+        },
+        ValueOption {
+            name: "minimapwidth",
+            get: |config| config.minimap_width.to_string(),
+            set: |config, value| {
+                config.minimap_width = parse_count(value)?;
+                Ok(())
+            },
+        },
+        ValueOption {
+            name: "pathdisplay",
+            get: |config| config.path_display.to_string(),
+            set: |config, value| {
+                config.path_display = PathDisplay::parse(value).ok_or_else(|| {
+                    format!(
+                        "expected name, relative, project or absolute, not \"{}\"",
+                        value
+                    )
+                })?;
+                Ok(())
+            },
+        },
+        ValueOption {
+            name: "statusformat",
+            get: |config| config.status_format.clone(),
+            set: |config, value| {
+                config.status_format = value.to_string();
+                Ok(())
+            },
+        },
+        // Leaving the value empty goes back to plain spaces
+        ValueOption {
+            name: "tabfill",
+            get: |config| config.tab_fill.to_string(),
+            set: |config, value| {
+                let mut chars = value.chars();
+                config.tab_fill = match (chars.next(), chars.next()) {
+                    (None, _) => ' ',
+                    (Some(fill), None) if fill.width() == Some(1) => fill,
+                    _ => {
+                        return Err(format!(
+                            "expected a single narrow character, not \"{}\"",
+                            value
+                        ))
+                    }
+                };
+                Ok(())
+            },
+        },
+        ValueOption {
+            name: "undolevels",
+            get: |config| config.undo_levels.to_string(),
+            set: |config, value| {
+                config.undo_levels = parse_count(value)?;
+                Ok(())
+            },
+        },
+        ValueOption {
+            name: "wordchars",
+            get: |config| config.word_chars.to_string(),
+            set: |config, value| {
+                config.word_chars = WordChars::new(value);
+                Ok(())
+            },
+        },
+    ];
+
+    fn option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        let option = Self::OPTIONS.iter().find(|option| option.name == name)?;
+        Some((option.get_mut)(self))
+    }
+
+    pub fn option(&self, name: &str) -> Option<bool> {
+        let option = Self::OPTIONS.iter().find(|option| option.name == name)?;
+        Some(*(option.get)(self))
+    }
+
+    /// The current value of an option from `VALUE_OPTIONS`, as `set` would take it.
+    pub fn value(&self, name: &str) -> Option<String> {
+        let option = Self::VALUE_OPTIONS
+            .iter()
+            .find(|option| option.name == name)?;
+        Some((option.get)(self))
+    }
+
+    /** Applies one setting: `name=value` for an option with a value, `name=on` or
+    `name=off` for an on/off one, or any of the forms `set_option` takes. Returns how the option reads now,
+    e.g. `scrollbar=on`. */
+    pub fn set(&mut self, setting: &str) -> Result<String, String> {
+        let Some((name, value)) = setting.split_once('=') else {
+            let (name, value) = self.set_option(setting)?;
+            let state = if value { "on" } else { "off" };
+            return Ok(format!("{}={}", name, state));
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if let Some(option) = self.option_mut(name) {
+            *option = parse_switch(value).map_err(|e| format!("{}: {}", name, e))?;
+            let state = if *option { "on" } else { "off" };
+            return Ok(format!("{}={}", name, state));
+        }
+        let option = Self::VALUE_OPTIONS
+            .iter()
+            .find(|option| option.name == name)
+            .ok_or_else(|| format!("Unknown option: {}", name))?;
+        (option.set)(self, value).map_err(|e| format!("{}: {}", name, e))?;
+        Ok(format!("{}={}", name, (option.get)(self)))
+    }
+
+    /** Applies a vim-style option setting: `name` turns it on, `noname` turns it off,
+    and `name!` or `invname` flips it. Returns the option's name and new value. */
+    pub fn set_option(&mut self, setting: &str) -> Result<(String, bool), String> {
+        let (name, value) = if let Some(name) = setting.strip_suffix('!') {
+            (name, None)
+        } else if let Some(name) = setting
+            .strip_prefix("inv")
+            .filter(|name| self.option(name).is_some())
+        {
+            (name, None)
+        } else if let Some(name) = setting
+            .strip_prefix("no")
+            .filter(|name| self.option(name).is_some())
+        {
+            (name, Some(false))
+        } else {
+            (setting, Some(true))
+        };
+        match self.option_mut(name) {
+            Some(option) => {
+                *option = value.unwrap_or(!*option);
+                Ok((name.to_string(), *option))
+            }
+            None => Err(format!("Unknown option: {}", name)),
+        }
+    }
+}
+
+/// Reads the value of an on/off option.
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(format!("expected on or off, not \"{}\"", value)),
+    }
+}
+
+/// Reads a setting that has to be a whole number above 0.
+fn parse_count(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("expected a number above 0, not \"{}\"", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_listed_option_can_be_set() {
+        let mut config = Config::default();
+        for option in Config::OPTIONS {
+            assert_eq!(
+                config.set_option(option.name),
+                Ok((option.name.to_string(), true))
+            );
+            assert_eq!(config.option(option.name), Some(true));
+            let off = format!("no{}", option.name);
+            assert_eq!(
+                config.set_option(&off),
+                Ok((option.name.to_string(), false))
+            );
+            assert_eq!(config.option(option.name), Some(false));
+        }
+        assert!(config.set_option("nosuchoption").is_err());
+    }
+
+    /// A config file in a fresh temporary directory, for `Config::from_file` to read.
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("stte-config-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn normalize_line_endings_from_the_config_file() {
+        let path = config_file("eol", "# work in LF\nnormalizeeol\n");
+        let (config, errors) = Config::from_file(&path).unwrap();
+        assert!(errors.is_empty());
+        assert!(config.normalize_line_endings);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn modelines_from_the_config_file() {
+        let path = config_file("modelines", "modelines\n");
+        let (config, errors) = Config::from_file(&path).unwrap();
+        assert!(errors.is_empty());
+        assert!(config.modelines);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn values_from_the_config_file() {
+        let path = config_file(
+            "values",
+            "scrollbar nominimap\nstatusformat = {file} | {row}:{col}\nindentwidth=2\nindentwidth = none\nfoo = 1\n",
+        );
+        let (config, errors) = Config::from_file(&path).unwrap();
+        assert!(config.scrollbar);
+        assert_eq!(config.status_format, "{file} | {row}:{col}");
+        assert_eq!(config.indent_width, 2);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors[0].ends_with(":4: indentwidth: expected a number above 0, not \"none\""));
+        assert!(errors[1].ends_with(":5: Unknown option: foo"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn set_reports_the_new_value() {
+        let mut config = Config::default();
+        assert_eq!(config.set("noscrollbar"), Ok("scrollbar=off".to_string()));
+        assert_eq!(config.set("wordchars=_-"), Ok("wordchars=_-".to_string()));
+        assert!(config.word_chars.contains('-'));
+        assert_eq!(config.value("wordchars"), Some("_-".to_string()));
+    }
+
+    #[test]
+    fn reported_settings_can_be_set_again() {
+        let mut config = Config::default();
+        for option in Config::OPTIONS {
+            for setting in [option.name.to_string(), format!("no{}", option.name)] {
+                let reported = config.set(&setting).unwrap();
+                let value = config.option(option.name);
+                config.set_option(&format!("{}!", option.name)).unwrap();
+                assert_eq!(config.set(&reported), Ok(reported.clone()));
+                assert_eq!(config.option(option.name), value);
+            }
+        }
+        for option in Config::VALUE_OPTIONS {
+            let reported = format!("{}={}", option.name, (option.get)(&config));
+            assert_eq!(config.set(&reported), Ok(reported.clone()));
+        }
+        assert_eq!(config.set("wrap = true"), Ok("wrap=on".to_string()));
+        assert_eq!(config.set("wrap=false"), Ok("wrap=off".to_string()));
+        assert_eq!(
+            config.set("wrap=maybe"),
+            Err("wrap: expected on or off, not \"maybe\"".to_string())
+        );
+    }
+
+    #[test]
+    fn on_off_settings_from_the_config_file() {
+        let path = config_file("switches", "wrap = on\nscrollbar=off\n");
+        let (config, errors) = Config::from_file(&path).unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(config.wrap);
+        assert!(!config.scrollbar);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn set_undo_levels() {
+        let mut config = Config::default();
+        assert_eq!(config.set("undolevels=50"), Ok("undolevels=50".to_string()));
+        assert_eq!(config.undo_levels, 50);
+        assert!(config.set("undolevels=0").is_err());
+    }
+
+    #[test]
+    fn set_tab_fill() {
+        let mut config = Config::default();
+        assert_eq!(config.set("tabfill = »"), Ok("tabfill=»".to_string()));
+        assert_eq!(config.tab_fill(), '»');
+        assert!(config.set("tabfill=->").is_err());
+        assert!(config.set("tabfill=中").is_err());
+        assert_eq!(config.tab_fill(), '»');
+        config.set("tabfill=").unwrap();
+        assert_eq!(config.tab_fill(), ' ');
+    }
+
+    #[test]
+    fn set_message_timeout() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.set("messagetimeout=1.5"),
+            Ok("messagetimeout=1.5".to_string())
+        );
+        assert_eq!(config.status_message_timeout, Duration::from_millis(1500));
+        assert!(config.set("messagetimeout=-1").is_err());
+        assert!(config.set("messagetimeout=soon").is_err());
+        assert_eq!(config.value("messagetimeout"), Some("1.5".to_string()));
+    }
+
+    #[test]
+    fn set_path_display() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.set("pathdisplay=name"),
+            Ok("pathdisplay=name".to_string())
+        );
+        assert_eq!(config.path_display, PathDisplay::Name);
+        assert!(config.set("pathdisplay=full").is_err());
+    }
+
+    #[test]
+    fn set_minimap() {
+        let mut config = Config::default();
+        assert!(Config::OPTIONS
+            .iter()
+            .any(|option| option.name == "minimap"));
+        config.set("minimap").unwrap();
+        assert_eq!(
+            config.set("minimapwidth=6"),
+            Ok("minimapwidth=6".to_string())
+        );
+        assert!(config.minimap);
+        assert_eq!(config.minimap_width, 6);
+        assert!(config.set("minimapwidth=0").is_err());
+    }
+
+    #[test]
+    fn set_cursor_shape() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.set("cursorshape=block"),
+            Ok("cursorshape=block".to_string())
+        );
+        assert_eq!(config.cursor_shape, CursorShape::Block);
+        assert!(config.set("cursorshape=beam").is_err());
+        assert_eq!(config.cursor_shape, CursorShape::Block);
+    }
+}
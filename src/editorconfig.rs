@@ -0,0 +1,157 @@
+use crate::buffer::LineEnding;
+use crate::glob;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/** The `.editorconfig` properties that apply to a file. Only the properties
+some section set are `Some`. */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EditorConfig {
+    pub use_tabs: Option<bool>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+/** Collects the properties for `path` from every `.editorconfig` file in its directory
+and the ones above it, stopping at a file marked `root = true`. Closer files win.
+Lines that can't be parsed and files that can't be read are skipped, and a warning
+for each is returned alongside the properties. */
+pub fn load(path: &Path) -> (EditorConfig, Vec<String>) {
+    let mut config = EditorConfig::default();
+    let mut warnings = Vec::new();
+    let Ok(path) = std::path::absolute(path) else {
+        return (config, warnings);
+    };
+
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let config_path = dir.join(".editorconfig");
+        match fs::read_to_string(&config_path) {
+            Ok(contents) => {
+                let is_root = is_root(&contents);
+                files.push((dir.to_path_buf(), config_path, contents));
+                if is_root {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warnings.push(format!("Can't read {}: {}", config_path.display(), e)),
+        }
+    }
+
+    // Apply the furthest file first so closer ones override it
+    for (dir, config_path, contents) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        apply_file(&mut config, &mut warnings, config_path, contents, &relative);
+    }
+    (config, warnings)
+}
+
+/// Whether the file's preamble, before the first section, says `root = true`.
+fn is_root(contents: &str) -> bool {
+    contents
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .any(|(key, value)| {
+            key.trim().eq_ignore_ascii_case("root") && value.trim().eq_ignore_ascii_case("true")
+        })
+}
+
+fn apply_file(
+    config: &mut EditorConfig,
+    warnings: &mut Vec<String>,
+    config_path: &Path,
+    contents: &str,
+    relative: &str,
+) {
+    // `None` in the preamble, then whether the current section matches the file
+    let mut in_matching_section = None;
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(pattern) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            in_matching_section = Some(section_matches(pattern, relative));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            warnings.push(format!(
+                "{}:{}: can't parse \"{}\"",
+                config_path.display(),
+                idx + 1,
+                line
+            ));
+            continue;
+        };
+        if in_matching_section == Some(true) {
+            apply_property(
+                config,
+                &key.trim().to_lowercase(),
+                &value.trim().to_lowercase(),
+            );
+        }
+    }
+}
+
+/// Unknown properties and values are ignored, as the spec asks.
+fn apply_property(config: &mut EditorConfig, key: &str, value: &str) {
+    let number = value.parse::<usize>().ok().filter(|number| *number > 0);
+    let flag = match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    };
+    match key {
+        "indent_style" => {
+            config.use_tabs = match value {
+                "tab" => Some(true),
+                "space" => Some(false),
+                _ => config.use_tabs,
+            }
+        }
+        // `tab` means indent by one tab stop, which `tab_width` already covers
+        "indent_size" if value == "tab" => config.indent_size = None,
+        "indent_size" => config.indent_size = number.or(config.indent_size),
+        "tab_width" => config.tab_width = number.or(config.tab_width),
+        "end_of_line" => {
+            config.end_of_line = match value {
+                "lf" => Some(LineEnding::LF),
+                "crlf" => Some(LineEnding::CRLF),
+                _ => config.end_of_line,
+            }
+        }
+        "trim_trailing_whitespace" => {
+            config.trim_trailing_whitespace = flag.or(config.trim_trailing_whitespace)
+        }
+        "insert_final_newline" => {
+            config.insert_final_newline = flag.or(config.insert_final_newline)
+        }
+        _ => {}
+    }
+}
+
+/** Whether a section's glob matches `relative`, the file's path from the directory
+holding the `.editorconfig`. Globs without a `/` match the file name in any directory. */
+fn section_matches(pattern: &str, relative: &str) -> bool {
+    let pattern = if let Some(anchored) = pattern.strip_prefix('/') {
+        anchored.to_string()
+    } else if pattern.contains('/') {
+        pattern.to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    glob::matches(&pattern, relative)
+}
@@ -1,10 +1,12 @@
+use crate::buffer::Buffer;
 use crossterm::event;
-use crossterm::event::{Event, KeyEvent};
-use std::time::Duration;
+use crossterm::event::Event;
+use std::time::{Duration, Instant};
 
 pub struct EventHandler;
 
 impl EventHandler {
+    /// Blocks until an event arrives. Used by prompts, which have nothing to do while waiting.
     pub fn get_events(&self) -> crossterm::Result<Event> {
         loop {
             if event::poll(Duration::from_millis(500))? {
@@ -12,4 +14,69 @@ impl EventHandler {
             }
         }
     }
+
+    /** Whether another event is already waiting. Keys arriving faster than anyone
+    types means they're being pasted by a terminal without bracketed paste. */
+    pub fn input_pending(&self) -> crossterm::Result<bool> {
+        event::poll(Duration::ZERO)
+    }
+
+    /// Waits up to `timeout` for an event, returning `None` if nothing happened.
+    pub fn poll_event(&self, timeout: Duration) -> crossterm::Result<Option<Event>> {
+        if event::poll(timeout)? {
+            event::read().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/** Work run from the main loop while there's no input. Returns `true` if it changed
+something that needs to be redrawn. */
+pub type IdleAction<E> = fn(&mut E, &mut Buffer) -> crossterm::Result<bool>;
+
+struct IdleTask<E> {
+    interval: Duration,
+    last_run: Instant,
+    action: IdleAction<E>,
+}
+
+/// Tasks that run periodically whenever the editor is waiting for input.
+pub struct IdleTasks<E> {
+    tasks: Vec<IdleTask<E>>,
+}
+
+impl<E> IdleTasks<E> {
+    pub fn new() -> Self {
+        Self { tasks: Vec::new() }
+    }
+
+    /// Runs `action` at most once every `interval`.
+    pub fn register(&mut self, interval: Duration, action: IdleAction<E>) {
+        self.tasks.push(IdleTask {
+            interval,
+            last_run: Instant::now(),
+            action,
+        });
+    }
+
+    /** The tasks whose interval has passed, which are counted as run from now.
+    They're returned rather than run here so they can borrow the editor that owns this list. */
+    pub fn due(&mut self) -> Vec<IdleAction<E>> {
+        let now = Instant::now();
+        self.tasks
+            .iter_mut()
+            .filter(|task| now.duration_since(task.last_run) >= task.interval)
+            .map(|task| {
+                task.last_run = now;
+                task.action
+            })
+            .collect()
+    }
+}
+
+impl<E> Default for IdleTasks<E> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,93 @@
+/** Whether `path` matches the glob `pattern`. Supports `*` (within a directory),
+`**` (across directories), `?`, `[...]` classes and `{a,b}` alternatives.
+Both are `/` separated and matched as a whole. */
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let path: Vec<char> = path.chars().collect();
+    expand_braces(pattern)
+        .iter()
+        .any(|glob| glob_matches(&glob.chars().collect::<Vec<_>>(), &path))
+}
+
+/// Expands `{a,b}` alternatives into separate globs, innermost first.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(close) = pattern.find('}') else {
+        return vec![pattern.to_string()];
+    };
+    let Some(open) = pattern[..close].rfind('{') else {
+        return vec![pattern.to_string()];
+    };
+    let alternatives = &pattern[open + 1..close];
+    if !alternatives.contains(',') {
+        // A single word in braces is matched literally
+        let literal = format!("{}{}", &pattern[..open], &pattern[open + 1..]);
+        let literal = literal.replacen('}', "", 1);
+        return expand_braces(&literal);
+    }
+    alternatives
+        .split(',')
+        .flat_map(|alternative| {
+            expand_braces(&format!(
+                "{}{}{}",
+                &pattern[..open],
+                alternative,
+                &pattern[close + 1..]
+            ))
+        })
+        .collect()
+}
+
+/// Matches `*` (within a directory), `**` (across directories), `?` and `[...]` classes.
+fn glob_matches(glob: &[char], path: &[char]) -> bool {
+    match glob.first() {
+        None => path.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => {
+            // `**/` also matches no directories at all
+            let rest = &glob[2..];
+            if rest.first() == Some(&'/') && glob_matches(&rest[1..], path) {
+                return true;
+            }
+            (0..=path.len()).any(|skip| glob_matches(rest, &path[skip..]))
+        }
+        Some('*') => {
+            let segment_len = path.iter().position(|ch| *ch == '/').unwrap_or(path.len());
+            (0..=segment_len).any(|skip| glob_matches(&glob[1..], &path[skip..]))
+        }
+        Some('?') => {
+            path.first().is_some_and(|ch| *ch != '/') && glob_matches(&glob[1..], &path[1..])
+        }
+        Some('[') => match glob.iter().position(|ch| *ch == ']') {
+            Some(close) if close > 1 => {
+                let Some(ch) = path.first() else {
+                    return false;
+                };
+                let (negated, class) = match glob[1] {
+                    '!' => (true, &glob[2..close]),
+                    _ => (false, &glob[1..close]),
+                };
+                class_contains(class, *ch) != negated
+                    && glob_matches(&glob[close + 1..], &path[1..])
+            }
+            _ => path.first() == Some(&'[') && glob_matches(&glob[1..], &path[1..]),
+        },
+        Some(literal) => path.first() == Some(literal) && glob_matches(&glob[1..], &path[1..]),
+    }
+}
+
+/// Whether a `[...]` class, given without its brackets, contains `ch`. Supports `a-z` ranges.
+fn class_contains(class: &[char], ch: char) -> bool {
+    let mut idx = 0;
+    while idx < class.len() {
+        if idx + 2 < class.len() && class[idx + 1] == '-' {
+            if (class[idx]..=class[idx + 2]).contains(&ch) {
+                return true;
+            }
+            idx += 3;
+        } else {
+            if class[idx] == ch {
+                return true;
+            }
+            idx += 1;
+        }
+    }
+    false
+}
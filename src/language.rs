@@ -0,0 +1,110 @@
+use std::path::Path;
+
+/// How a language writes comments. At least one of the two is always set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommentSyntax {
+    /// Starts a comment running to the end of the line, e.g. `//`.
+    pub line: Option<&'static str>,
+    /// Open and close delimiters of a block comment, e.g. `/*` and `*/`.
+    pub block: Option<(&'static str, &'static str)>,
+}
+
+const C_LIKE: CommentSyntax = CommentSyntax {
+    line: Some("//"),
+    block: Some(("/*", "*/")),
+};
+
+/// The comment syntax of the file at `path`, going by its extension.
+pub fn comment_syntax(path: Option<&Path>) -> Option<CommentSyntax> {
+    let extension = path?.extension()?.to_str()?.to_ascii_lowercase();
+    let syntax = match extension.as_str() {
+        "c" | "cc" | "cpp" | "cs" | "go" | "h" | "hpp" | "java" | "js" | "jsx" | "kt" | "rs"
+        | "php" | "scala" | "swift" | "ts" | "tsx" => C_LIKE,
+        "css" => CommentSyntax {
+            line: None,
+            block: Some(("/*", "*/")),
+        },
+        "html" | "xml" | "md" => CommentSyntax {
+            line: None,
+            block: Some(("<!--", "-->")),
+        },
+        "py" | "sh" | "bash" | "rb" | "toml" | "yaml" | "yml" | "conf" => CommentSyntax {
+            line: Some("#"),
+            block: None,
+        },
+        "lua" | "sql" | "hs" => CommentSyntax {
+            line: Some("--"),
+            block: None,
+        },
+        "vim" => CommentSyntax {
+            line: Some("\""),
+            block: None,
+        },
+        _ => return None,
+    };
+    Some(syntax)
+}
+
+/// HTML elements that never have a closing tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Whether the file at `path` is markup whose tags can be closed automatically.
+pub fn closes_tags(path: Option<&Path>) -> bool {
+    path.and_then(|path| path.extension())
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            matches!(
+                ext.to_ascii_lowercase().as_str(),
+                "htm" | "html" | "svg" | "vue" | "xhtml" | "xml"
+            )
+        })
+}
+
+/** The name of the innermost tag still open at the end of `text`, i.e. the one a
+`</` typed there would close. Comments, doctypes, processing instructions,
+self-closing tags and HTML void elements don't open anything. */
+pub fn unclosed_tag(text: &str) -> Option<String> {
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with(['!', '?']) || tag.ends_with('/') {
+            continue;
+        }
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name = tag
+            .split(|ch: char| ch.is_whitespace())
+            .next()
+            .unwrap_or("");
+        if name.is_empty() {
+            continue;
+        }
+        if closing {
+            // Anything left open inside the closed tag is closed along with it
+            if let Some(idx) = open
+                .iter()
+                .rposition(|open| open.eq_ignore_ascii_case(name))
+            {
+                open.truncate(idx);
+            }
+        } else if !VOID_ELEMENTS.contains(&name.to_ascii_lowercase().as_str()) {
+            open.push(name);
+        }
+    }
+    open.last().map(|name| name.to_string())
+}
@@ -0,0 +1,17 @@
+pub mod buffer;
+pub mod build;
+pub mod chord;
+pub mod clipboard;
+pub mod config;
+pub mod editorconfig;
+pub mod event_handler;
+pub mod glob;
+pub mod language;
+pub mod modeline;
+pub mod number;
+pub mod path;
+pub mod picker;
+pub mod project;
+pub mod quickfix;
+pub mod registers;
+pub mod screen;
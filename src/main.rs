@@ -1,16 +1,48 @@
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState};
+use crossterm::event::{
+    DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind,
+    KeyEventState,
+};
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
-use crossterm::{event, execute, terminal};
+use crossterm::tty::IsTty;
+use crossterm::{cursor, event, execute, terminal};
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::io::stdout;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
 
-use buffer::Buffer;
-use screen::Screen;
+use stte_rs::buffer::{
+    caret_notation, Backup, Buffer, BufferError, IndentStyle, SearchOptions, Status, TabStops,
+    WordChars,
+};
+use stte_rs::build::Build;
+use stte_rs::chord::{key_name, resolve, Resolution};
+use stte_rs::clipboard::SystemClipboard;
+use stte_rs::config::{config_path, template_for, Config};
+use stte_rs::editorconfig;
+use stte_rs::event_handler::{EventHandler, IdleTasks};
+use stte_rs::language::{closes_tags, comment_syntax, unclosed_tag};
+use stte_rs::modeline::find_modeline;
+use stte_rs::path::{expand_path, PathCompletion};
+use stte_rs::picker::Picker;
+use stte_rs::project::{spawn_file_scan, spawn_grep};
+use stte_rs::quickfix::{QuickfixEntry, QuickfixList};
+use stte_rs::registers::Registers;
+use stte_rs::screen::{PromptHandler, PromptKind, Screen, Severity};
 
-mod buffer;
-mod event_handler;
-mod screen;
+/// How long the main loop waits for input before running idle tasks.
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+/// How often a running build is checked on, which also paces its spinner.
+const BUILD_POLL_TIMEOUT: Duration = Duration::from_millis(50);
+const CTRL_SHIFT: event::KeyModifiers =
+    event::KeyModifiers::CONTROL.union(event::KeyModifiers::SHIFT);
+/// How long a key sequence waits for its next key before it's cancelled.
+const CHORD_TIMEOUT: Duration = Duration::from_secs(2);
+/// Numbered backups kept by `backup numbered` when no count is given.
+const DEFAULT_BACKUPS_KEPT: usize = 5;
 
 /** The `CleanUp` struct is used to disable raw_mode
 when the struct goes out of scope.
@@ -19,28 +51,182 @@ and disabling raw_mode in the drop method.
 This prevents the terminal from remaining in raw mode
 if an error occurs after it's been set to raw mode
 and the program exits. */
-struct CleanUp;
+struct CleanUp {
+    // Without the alternate screen the editor drew over the main one, which is cleared instead
+    alternate_screen: bool,
+}
 
 impl Drop for CleanUp {
     fn drop(&mut self) {
-        execute!(stdout(), LeaveAlternateScreen).unwrap();
-        terminal::disable_raw_mode().expect("Could not turn off raw mode");
+        // Errors are ignored so the rest of the terminal still gets restored
+        let _ = execute!(
+            stdout(),
+            cursor::SetCursorStyle::DefaultUserShape,
+            cursor::EnableBlinking,
+            DisableBracketedPaste
+        );
+        let _ = if self.alternate_screen {
+            execute!(stdout(), LeaveAlternateScreen)
+        } else {
+            execute!(
+                stdout(),
+                terminal::Clear(terminal::ClearType::All),
+                cursor::MoveTo(0, 0)
+            )
+        };
+        let _ = terminal::disable_raw_mode();
     }
 }
 
+/// Reads a Unicode code point written in hex, with or without a `U+` or `0x` in front.
+fn parse_code_point(code: &str) -> Result<char, String> {
+    let code = code.trim();
+    let hex = ["U+", "u+", "0x", "0X"]
+        .iter()
+        .find_map(|prefix| code.strip_prefix(prefix))
+        .unwrap_or(code);
+    let value =
+        u32::from_str_radix(hex, 16).map_err(|_| format!("\"{}\" isn't a hex number", code))?;
+    char::from_u32(value).ok_or_else(|| match value {
+        0xD800..=0xDFFF => format!("U+{:04X} is a surrogate, not a character", value),
+        _ => format!("U+{:X} is past the last code point, U+10FFFF", value),
+    })
+}
+
+/// The character a key stands for when inserted literally, with Ctrl+letter giving control characters.
+fn literal_char(key: KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(event::KeyModifiers::CONTROL) => {
+            match c.to_ascii_uppercase() {
+                c @ '@'..='_' => Some(((c as u8) ^ 0x40) as char),
+                '?' => Some('\x7f'),
+                _ => None,
+            }
+        }
+        KeyCode::Char(c) if key.modifiers.contains(event::KeyModifiers::SHIFT) => {
+            c.to_uppercase().next()
+        }
+        KeyCode::Char(c) => Some(c),
+        KeyCode::Tab => Some('\t'),
+        KeyCode::Esc => Some('\x1b'),
+        KeyCode::Backspace => Some('\x7f'),
+        _ => None,
+    }
+}
+
+/// Terminals that declare themselves dumb can't switch screens, even if writing the request works.
+fn supports_alternate_screen() -> bool {
+    env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
 struct TextEditor {
+    config: Config,
     screen: Screen,
-    event_handler: event_handler::EventHandler,
+    // Search toggles and the last query are kept for the whole session
+    search_options: SearchOptions,
+    last_search: Option<String>,
+    // Locations from the last grep, kept so they can be stepped through
+    quickfix: QuickfixList,
+    registers: Registers,
+    /// Found once at startup, since connecting to it or searching `PATH` for the tools takes time.
+    clipboard: Option<SystemClipboard>,
+    // Cursor and scroll position of the files opened before, to go back to them
+    views: HashMap<PathBuf, (usize, usize)>,
+    event_handler: EventHandler,
+    idle_tasks: IdleTasks<TextEditor>,
+    // When the config file was last changed as of loading it, to notice edits to it
+    config_modified: Option<SystemTime>,
+    // Set when the buffer, cursor, scroll position or messages change, so the screen needs redrawing
+    dirty: bool,
 }
 
 impl TextEditor {
     fn new() -> Self {
+        let mut idle_tasks = IdleTasks::new();
+        idle_tasks.register(Duration::from_millis(250), |editor: &mut TextEditor, _| {
+            Ok(editor
+                .screen
+                .expire_status_message(editor.config.status_message_timeout))
+        });
+        idle_tasks.register(Duration::from_secs(1), |editor: &mut TextEditor, buffer| {
+            let modified = config_path()
+                .and_then(|path| path.metadata().ok())
+                .and_then(|metadata| metadata.modified().ok());
+            if modified.is_none() || modified == editor.config_modified {
+                return Ok(false);
+            }
+            editor.load_config(buffer, true)?;
+            Ok(true)
+        });
         Self {
+            config: Config::default(),
             screen: Screen::new(),
-            event_handler: event_handler::EventHandler,
+            search_options: SearchOptions::default(),
+            last_search: None,
+            quickfix: QuickfixList::default(),
+            registers: Registers::default(),
+            clipboard: SystemClipboard::detect(),
+            views: HashMap::new(),
+            event_handler: EventHandler,
+            idle_tasks,
+            config_modified: None,
+            dirty: true,
         }
     }
 
+    /** Loads the config file, if there is one, in place of the current settings and
+    applies it to the screen and buffer. With `announce`, says so once it's loaded,
+    and warns if there's no file. Problems with the file are always reported. */
+    fn load_config(&mut self, buffer: &mut Buffer, announce: bool) -> crossterm::Result<()> {
+        let Some(path) = config_path() else {
+            return Ok(());
+        };
+        self.config_modified = path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        match Config::from_file(&path) {
+            Ok((config, errors)) => {
+                self.config = config;
+                self.apply_config(buffer)?;
+                if let Some(error) = errors.first() {
+                    let more = match errors.len() {
+                        1 => String::new(),
+                        count => format!(" (and {} more)", count - 1),
+                    };
+                    self.screen
+                        .set_status_message(format!("Error: {}{}", error, more), Severity::Error);
+                } else if announce {
+                    self.screen
+                        .set_status_message("Config reloaded".to_string(), Severity::Info);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if announce {
+                    self.screen.set_status_message(
+                        format!("No config file at {}", path.display()),
+                        Severity::Warning,
+                    );
+                }
+            }
+            Err(e) => self.screen.set_status_message(
+                format!("Error: Can't read {}: {}", path.display(), e),
+                Severity::Error,
+            ),
+        }
+        Ok(())
+    }
+
+    /** Applies the settings that are copied out of the config when they change. The rest
+    are read from it whenever the screen is drawn, so a redraw is enough for them. */
+    fn apply_config(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        self.screen.apply_cursor_style(&self.config)?;
+        buffer.set_word_chars(self.config.word_chars.clone());
+        buffer.set_undo_levels(self.config.undo_levels);
+        self.dirty = true;
+        Ok(())
+    }
+
     fn process_keypress(
         &mut self,
         buffer: &mut Buffer,
@@ -52,75 +238,357 @@ impl TextEditor {
                 modifiers: event::KeyModifiers::CONTROL,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
-            } => return Ok(false),
+            } => {
+                if matches!(buffer.status(), Status::Modified) {
+                    return Ok(!self
+                        .screen
+                        .confirm("Buffer has unsaved changes. Quit anyway? (y/n)")?);
+                }
+                return Ok(false);
+            }
             KeyEvent {
-                code: KeyCode::Left,
+                code:
+                    code @ (KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Home
+                    | KeyCode::End
+                    | KeyCode::PageUp
+                    | KeyCode::PageDown),
                 modifiers: event::KeyModifiers::NONE,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                buffer.move_cursor_left();
+                buffer.clear_selection();
+                self.move_cursor(buffer, code);
             }
+            // Moving with Shift held selects from where the cursor was
             KeyEvent {
-                code: KeyCode::Right,
-                modifiers: event::KeyModifiers::NONE,
+                code:
+                    code @ (KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Home
+                    | KeyCode::End
+                    | KeyCode::PageUp
+                    | KeyCode::PageDown),
+                modifiers: event::KeyModifiers::SHIFT,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                buffer.move_cursor_right();
+                buffer.start_selection();
+                self.move_cursor(buffer, code);
             }
             KeyEvent {
-                code: KeyCode::Up,
-                modifiers: event::KeyModifiers::NONE,
+                code: code @ (KeyCode::Left | KeyCode::Right),
+                modifiers: event::KeyModifiers::CONTROL,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                buffer.move_cursor_up();
+                buffer.clear_selection();
+                self.move_cursor_word(buffer, code);
             }
             KeyEvent {
-                code: KeyCode::Down,
-                modifiers: event::KeyModifiers::NONE,
+                code: code @ (KeyCode::Left | KeyCode::Right),
+                modifiers: CTRL_SHIFT,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                buffer.move_cursor_down();
+                buffer.start_selection();
+                self.move_cursor_word(buffer, code);
             }
             KeyEvent {
                 code: KeyCode::Char('s'),
                 modifiers: event::KeyModifiers::CONTROL,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
-            } => match buffer.save() {
-                Ok(message) => self.screen.set_status_message(message),
-                Err(e) => self.screen.set_status_message(format!("Error: {}", e)),
-            },
+            } => self.save(buffer)?,
             KeyEvent {
-                code: KeyCode::Enter,
-                modifiers: _,
+                code: KeyCode::Char('f'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.incremental_search(buffer)?,
+            // Only terminals that report modifiers on Backspace can send Ctrl+Backspace
+            KeyEvent {
+                code: KeyCode::Char('w'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            }
+            | KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: event::KeyModifiers::CONTROL,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                buffer.insert_newline()?;
+                if !buffer.delete_selection() {
+                    buffer.delete_word_backward()?;
+                }
             }
             KeyEvent {
-                code: KeyCode::Char(c),
-                modifiers,
+                code: KeyCode::Backspace,
+                modifiers: event::KeyModifiers::NONE,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            }
+            // Some terminals send ^H for Backspace, which arrives as Ctrl+H
+            | KeyEvent {
+                code: KeyCode::Char('h'),
+                modifiers: event::KeyModifiers::CONTROL,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                if modifiers.contains(event::KeyModifiers::SHIFT) {
-                    buffer.insert_char(c.to_uppercase().next().unwrap_or(c));
-                } else {
-                    buffer.insert_char(c);
+                if !buffer.delete_selection() {
+                    buffer.delete_char()?;
                 }
             }
             KeyEvent {
-                code: KeyCode::Backspace,
+                code: KeyCode::Char('r'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.query_replace(buffer)?,
+            KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.find_file(buffer)?,
+            KeyEvent {
+                code: KeyCode::Char('a'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.increment_number(buffer, 1),
+            KeyEvent {
+                code: KeyCode::Char('a'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.increment_number(buffer, -1),
+            KeyEvent {
+                code: KeyCode::Char('c' | 'C'),
+                modifiers: CTRL_SHIFT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.copy_to_system(buffer),
+            KeyEvent {
+                code: KeyCode::Char('v' | 'V'),
+                modifiers: CTRL_SHIFT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.paste_from_system(buffer),
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.yank_line(buffer, None, false),
+            KeyEvent {
+                code: KeyCode::Char('x'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.yank_line(buffer, None, true),
+            KeyEvent {
+                code: KeyCode::Char('v'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.paste_at_cursor(buffer),
+            KeyEvent {
+                code: KeyCode::Char('v'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.quoted_insert(buffer)?,
+            KeyEvent {
+                code: KeyCode::Char('z'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => {
+                if buffer.undo().is_none() {
+                    self.screen
+                        .set_status_message("Nothing to undo".to_string(), Severity::Info);
+                }
+            }
+            // Terminals differ in whether Shift changes the letter along with the modifiers
+            KeyEvent {
+                code: KeyCode::Char('y'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            }
+            | KeyEvent {
+                code: KeyCode::Char('z' | 'Z'),
+                modifiers: CTRL_SHIFT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => {
+                if buffer.redo().is_none() {
+                    self.screen
+                        .set_status_message("Nothing to redo".to_string(), Severity::Info);
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('k'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.chord(buffer)?,
+            // Most terminals send Ctrl+/ as Ctrl+7
+            KeyEvent {
+                code: KeyCode::Char('/' | '7'),
+                modifiers: event::KeyModifiers::CONTROL,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.toggle_comment(buffer),
+            KeyEvent {
+                code: KeyCode::F(3),
+                modifiers: event::KeyModifiers::NONE,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.repeat_search(buffer, true),
+            KeyEvent {
+                code: KeyCode::F(3),
+                modifiers: event::KeyModifiers::SHIFT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.repeat_search(buffer, false),
+            KeyEvent {
+                code: KeyCode::F(4),
                 modifiers: event::KeyModifiers::NONE,
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
+            } => self.step_quickfix(buffer, true)?,
+            KeyEvent {
+                code: KeyCode::F(4),
+                modifiers: event::KeyModifiers::SHIFT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.step_quickfix(buffer, false)?,
+            KeyEvent {
+                code: KeyCode::Char('*'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.count_word_at_cursor(buffer),
+            KeyEvent {
+                code: KeyCode::Char('t'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
             } => {
-                buffer.delete_char()?;
+                let toggled = buffer.toggle_line_indent();
+                if !toggled {
+                    self.screen.set_status_message(
+                        "Cursor is not in the indentation".to_string(),
+                        Severity::Warning,
+                    );
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('u'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self
+                .screen
+                .set_status_message(buffer.describe_char_at_cursor(), Severity::Info),
+            KeyEvent {
+                code: KeyCode::Char('x'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => {
+                if let Some(command) = self.screen.prompt(PromptKind::Command, ":")? {
+                    self.run_command(buffer, &command)?;
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char('z'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.toggle_zen(),
+            KeyEvent {
+                code: KeyCode::Char('h'),
+                modifiers: event::KeyModifiers::ALT,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => {
+                self.config.highlight_cursor_word = !self.config.highlight_cursor_word;
+                let state = if self.config.highlight_cursor_word {
+                    "on"
+                } else {
+                    "off"
+                };
+                self.screen
+                    .set_status_message(format!("Word highlighting {}", state), Severity::Info);
+            }
+            KeyEvent {
+                code: KeyCode::Insert,
+                modifiers: event::KeyModifiers::NONE,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => self.config.overwrite = !self.config.overwrite,
+            KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: _,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => {
+                buffer.delete_selection();
+                // Pasted lines already carry their indentation
+                if self.config.auto_indent && !self.event_handler.input_pending()? {
+                    let style = buffer
+                        .indent_style()
+                        .unwrap_or_else(|| self.config.indent_style());
+                    let smart = self
+                        .config
+                        .smart_indents(buffer.file_path().map(PathBuf::as_path));
+                    buffer.insert_newline_indented(style, smart)?;
+                } else {
+                    buffer.insert_newline()?;
+                }
+            }
+            KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::NONE,
+            } => {
+                let c = if modifiers.contains(event::KeyModifiers::SHIFT) {
+                    c.to_uppercase().next().unwrap_or(c)
+                } else {
+                    c
+                };
+                // Typing replaces the selection
+                buffer.delete_selection();
+                if matches!(c, '}' | ']' | ')')
+                    && self.config.auto_indent
+                    && !self.event_handler.input_pending()?
+                    && self
+                        .config
+                        .smart_indents(buffer.file_path().map(PathBuf::as_path))
+                {
+                    let style = buffer
+                        .indent_style()
+                        .unwrap_or_else(|| self.config.indent_style());
+                    buffer.dedent_before_cursor(style);
+                }
+                if self.config.overwrite {
+                    buffer.overwrite_char(c);
+                } else {
+                    buffer.insert_char(c);
+                    if c == '/' && self.config.close_tags {
+                        self.close_tag(buffer);
+                    }
+                }
             }
             KeyEvent {
                 code: KeyCode::Tab,
@@ -128,54 +596,1335 @@ impl TextEditor {
                 kind: KeyEventKind::Press,
                 state: KeyEventState::NONE,
             } => {
-                buffer.insert_char('\t');
+                let style = buffer
+                    .indent_style()
+                    .unwrap_or_else(|| self.config.indent_style());
+                buffer.insert_indent(style);
+            }
+            // Keys we don't handle leave the screen as it was
+            _ => return Ok(true),
+        }
+        self.dirty = true;
+        Ok(true)
+    }
+
+    /// Moves the cursor for an arrow key, Home, End, Page Up or Page Down.
+    fn move_cursor(&mut self, buffer: &mut Buffer, code: KeyCode) {
+        match code {
+            KeyCode::Left => {
+                let moved = buffer.move_cursor_left();
+                if !moved && self.config.wrap_cursor {
+                    buffer.move_to_previous_line_end();
+                }
+            }
+            KeyCode::Right => {
+                let moved = buffer.move_cursor_right();
+                if !moved && self.config.wrap_cursor {
+                    buffer.move_to_next_line_start();
+                }
+            }
+            KeyCode::Home => buffer.move_cursor_line_start(),
+            KeyCode::End => buffer.move_cursor_line_end(),
+            KeyCode::Up => {
+                buffer.move_cursor_up();
+            }
+            KeyCode::Down => {
+                buffer.move_cursor_down();
+            }
+            // The cursor stays on the same screen row, with the text moving under it
+            KeyCode::PageUp | KeyCode::PageDown => {
+                let row = self.screen.cursor_screen_row(buffer);
+                let rows = self.screen.page_rows();
+                if code == KeyCode::PageUp {
+                    buffer.move_cursor_page_up(rows);
+                } else {
+                    buffer.move_cursor_page_down(rows);
+                }
+                self.screen.scroll_cursor_to_row(buffer, row);
             }
             _ => {}
         }
+    }
+
+    /** Moves the cursor a word left or right for Ctrl and an arrow key. It stops at
+    the ends of the line, and only goes on to the next line when `wrap_cursor` is set. */
+    fn move_cursor_word(&mut self, buffer: &mut Buffer, code: KeyCode) {
+        if code == KeyCode::Left {
+            let moved = buffer.move_cursor_word_left();
+            if !moved && self.config.wrap_cursor {
+                buffer.move_to_previous_line_end();
+            }
+        } else {
+            let moved = buffer.move_cursor_word_right();
+            if !moved && self.config.wrap_cursor {
+                buffer.move_to_next_line_start();
+            }
+        }
+    }
+
+    /// Lets the user fuzzily pick a file in the project and opens it.
+    fn find_file(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let root = buffer.project_root().to_path_buf();
+        self.find_file_in(buffer, root)
+    }
+
+    /// Lets the user fuzzily pick a file under `root` and opens it.
+    fn find_file_in(&mut self, buffer: &mut Buffer, root: PathBuf) -> crossterm::Result<()> {
+        // The scan quietly skips directories it can't read, so say so up front for the root
+        if let Err(e) = fs::read_dir(&root) {
+            self.screen.set_status_message(
+                format!("Error: Can't open {}: {}", root.display(), e),
+                Severity::Error,
+            );
+            return Ok(());
+        }
+        let mut picker = Picker::streaming(spawn_file_scan(root.clone()));
+        let picked = self.screen.pick("Open: ", &mut picker)?;
+        // Dropping the picker stops the scan if it's still going
+        drop(picker);
+        if let Some(relative) = picked {
+            let path = root.join(relative);
+            self.open_file(buffer, &path.to_string_lossy())?;
+        }
+        Ok(())
+    }
+
+    /** Loads the file at `path` into a new buffer, or an empty one if it can't be
+    read, and starts a file that doesn't exist yet from its template. */
+    fn open_buffer(&mut self, path: &str) -> crossterm::Result<Buffer> {
+        let mut buffer = match self.load_buffer(path) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                self.screen
+                    .set_status_message(error.to_string(), Severity::Error);
+                let mut buffer = Buffer::new(Some(PathBuf::from(path))); // Create a buffer if there's an error but a path is still provided
+                self.apply_file_settings(&mut buffer);
+                buffer
+            }
+        };
+        // Only files that don't exist yet start from a template, never ones on disk
+        if buffer.is_empty() && !Path::new(path).exists() {
+            if let Some(template) = template_for(Path::new(path)) {
+                buffer.insert_str(&buffer.with_line_endings(&template));
+                buffer.set_cursor_pos(0);
+            }
+        }
+        Ok(buffer)
+    }
+
+    /** Loads the file at `path` into a new buffer, showing progress while it loads,
+    and applies the settings that depend on the file. */
+    fn load_buffer(&mut self, path: &str) -> Result<Buffer, BufferError> {
+        let loaded = Buffer::from_path_with_progress(path, |read, total| {
+            let _ = self.screen.show_progress("Loading", Some((read, total)));
+        });
+        self.screen.clear_progress()?;
+        let mut buffer = loaded?;
+        self.apply_file_settings(&mut buffer);
+        Ok(buffer)
+    }
+
+    /// Applies the config and the settings found in or next to the buffer's file.
+    fn apply_file_settings(&mut self, buffer: &mut Buffer) {
+        if self.config.normalize_line_endings {
+            buffer.normalize_line_endings();
+        }
+        buffer.set_word_chars(self.config.word_chars.clone());
+        buffer.set_undo_levels(self.config.undo_levels);
+        if self.config.editorconfig {
+            self.apply_editorconfig(buffer);
+        }
+        // Modelines come last, since they're the most specific to the file
+        if self.config.modelines {
+            self.apply_modeline(buffer);
+        }
+        buffer.set_tab_stops(self.config.tab_stops.clone());
+    }
+
+    /** Replaces the current buffer with the file at `path`, checking first if there are
+    unsaved changes. Files opened before come back with the cursor and scroll position
+    they were left with. Returns `false` if the user chose to keep the current buffer. */
+    fn open_file(&mut self, buffer: &mut Buffer, path: &str) -> crossterm::Result<bool> {
+        if matches!(buffer.status(), Status::Modified)
+            && !self
+                .screen
+                .confirm("Buffer has unsaved changes. Open anyway? (y/n)")?
+        {
+            return Ok(false);
+        }
+        if let Some(old_path) = buffer
+            .file_path()
+            .and_then(|path| std::path::absolute(path).ok())
+        {
+            let view = (buffer.cursor_pos(), self.screen.scroll_offset());
+            self.views.insert(old_path, view);
+        }
+        *buffer = self.open_buffer(path)?;
+        let view = std::path::absolute(path)
+            .ok()
+            .and_then(|path| self.views.get(&path).copied());
+        let (cursor_pos, scroll_offset) = view.unwrap_or_default();
+        buffer.set_cursor_pos(cursor_pos);
+        self.screen.set_scroll_offset(scroll_offset);
         Ok(true)
     }
 
-    fn process_events(&mut self, buffer: &mut Buffer) -> crossterm::Result<bool> {
-        match self.event_handler.get_events()? {
-            Event::Key(keyEvent) => {
-                return self.process_keypress(buffer, keyEvent);
+    /** Moves the cursor to a line and column, counting from 1, in the file at `path`,
+    opening it first unless it's already the current buffer. */
+    fn open_location(
+        &mut self,
+        buffer: &mut Buffer,
+        path: &Path,
+        line: usize,
+        column: usize,
+    ) -> crossterm::Result<()> {
+        let same_file = buffer.file_path().is_some_and(|current| {
+            std::path::absolute(current).ok() == std::path::absolute(path).ok()
+        });
+        if same_file || self.open_file(buffer, &path.to_string_lossy())? {
+            buffer.move_to(line.saturating_sub(1), column.saturating_sub(1));
+        }
+        Ok(())
+    }
+
+    /** Throws away unsaved changes by loading the file again from disk, after asking
+    if there are any. The cursor stays on the same line and column where they still
+    exist. The revert is a single undo step, so undoing it brings the changes back. */
+    fn revert(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let Some(path) = buffer.file_path().cloned() else {
+            self.screen.set_status_message(
+                "Error: Buffer has no file to revert to".to_string(),
+                Severity::Error,
+            );
+            return Ok(());
+        };
+        if !path.exists() {
+            self.screen.set_status_message(
+                format!("Error: {} hasn't been saved yet", path.display()),
+                Severity::Error,
+            );
+            return Ok(());
+        }
+        if matches!(buffer.status(), Status::Modified)
+            && !self
+                .screen
+                .confirm("Discard unsaved changes and revert to the saved file? (y/n)")?
+        {
+            return Ok(());
+        }
+        let scroll_offset = self.screen.scroll_offset();
+        if let Err(error) = buffer.revert(|path| self.load_buffer(&path.to_string_lossy())) {
+            self.screen
+                .set_status_message(format!("Error: Can't revert: {}", error), Severity::Error);
+            return Ok(());
+        }
+        self.screen.set_scroll_offset(scroll_offset);
+        self.screen.set_status_message(
+            format!("Reverted to the saved {}", path.display()),
+            Severity::Info,
+        );
+        Ok(())
+    }
+
+    /** Searches the buffer as the query is typed, moving the cursor to the first match
+    from where the search started and highlighting it. Ctrl+F or Down goes to the next
+    match and Up to the previous one. Enter leaves the cursor on the match, and Esc puts
+    it and the view back where they were. */
+    fn incremental_search(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let origin = buffer.cursor_pos();
+        let origin_scroll = self.screen.scroll_offset();
+        let mut query = String::new();
+        let mut current: Option<usize> = None;
+        loop {
+            match current {
+                Some(pos) => buffer.set_cursor_pos(pos),
+                None => buffer.set_cursor_pos(origin),
+            }
+            self.screen
+                .set_search_highlight(&query, self.search_options, current);
+            self.screen.display_buffer(buffer, &self.config)?;
+            let not_found = if current.is_none() && !query.is_empty() {
+                " (not found)"
+            } else {
+                ""
+            };
+            let prompt = format!(
+                "Search: {}{}{}",
+                self.search_options.indicator(),
+                query,
+                not_found
+            );
+            let key = self.screen.read_key(&prompt)?;
+            if self.search_options.on_key(&key, &mut query) {
+                current = buffer.find_next(&query, origin, self.search_options);
+                continue;
+            }
+            let control = key.modifiers.contains(event::KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Esc => {
+                    buffer.set_cursor_pos(origin);
+                    self.screen.set_scroll_offset(origin_scroll);
+                    break;
+                }
+                KeyCode::Enter => {
+                    if !query.is_empty() {
+                        self.last_search = Some(query);
+                    }
+                    break;
+                }
+                KeyCode::Backspace => {
+                    query.pop();
+                    current = buffer.find_next(&query, origin, self.search_options);
+                }
+                code if code == KeyCode::Down || (control && code == KeyCode::Char('f')) => {
+                    if let Some(pos) = current {
+                        current = buffer.find_next(&query, pos + 1, self.search_options);
+                    }
+                }
+                KeyCode::Up => {
+                    if let Some(pos) = current {
+                        current = buffer.find_prev(&query, pos, self.search_options);
+                    }
+                }
+                KeyCode::Char(c)
+                    if !control && !key.modifiers.contains(event::KeyModifiers::ALT) =>
+                {
+                    query.push(c);
+                    // Searching from the current match keeps it while it still matches
+                    let from = current.unwrap_or(origin);
+                    current = buffer.find_next(&query, from, self.search_options);
+                }
+                _ => {}
+            }
+        }
+        self.screen.clear_search_highlight();
+        Ok(())
+    }
+
+    /** Searches every file in the project for `query`, or for a query prompted for
+    when it's `None`, and jumps to the match the user picks from the results. */
+    fn grep(&mut self, buffer: &mut Buffer, query: Option<String>) -> crossterm::Result<()> {
+        let query = match query {
+            Some(query) => query,
+            None => {
+                let last_search = self.last_search.clone().unwrap_or_default();
+                match self.screen.prompt_with(
+                    PromptKind::Search,
+                    "Search project: ",
+                    &last_search,
+                    &mut self.search_options,
+                )? {
+                    Some(query) if !query.is_empty() => query,
+                    _ => return Ok(()),
+                }
+            }
+        };
+        self.last_search = Some(query.clone());
+        let root = buffer.project_root().to_path_buf();
+        let results = spawn_grep(
+            root.clone(),
+            query,
+            self.search_options,
+            buffer.word_chars().clone(),
+        );
+        let mut picker = Picker::streaming(results);
+        let picked = self.screen.pick("Results: ", &mut picker)?;
+        // Whatever arrived becomes the quickfix list, even if the search was cut short
+        self.quickfix = QuickfixList::new(
+            picker
+                .items()
+                .iter()
+                .filter_map(|item| QuickfixEntry::parse(item, &root))
+                .collect(),
+        );
+        drop(picker);
+        if let Some(entry) = picked.and_then(|item| QuickfixEntry::parse(&item, &root)) {
+            if let Some(idx) = self.quickfix.entries().iter().position(|e| *e == entry) {
+                self.quickfix.select(idx);
+            }
+            self.open_location(buffer, &entry.path, entry.line, entry.column)?;
+        }
+        Ok(())
+    }
+
+    /// Jumps to the next quickfix entry, or the previous one when `forward` is `false`.
+    fn step_quickfix(&mut self, buffer: &mut Buffer, forward: bool) -> crossterm::Result<()> {
+        if self.quickfix.is_empty() {
+            self.screen
+                .set_status_message("Quickfix list is empty".to_string(), Severity::Warning);
+            return Ok(());
+        }
+        let entry = if forward {
+            self.quickfix.select_next()
+        } else {
+            self.quickfix.select_previous()
+        };
+        let Some(entry) = entry.cloned() else {
+            let end = if forward { "last" } else { "first" };
+            self.screen.set_status_message(
+                format!("Already at the {} quickfix entry", end),
+                Severity::Warning,
+            );
+            return Ok(());
+        };
+        self.open_location(buffer, &entry.path, entry.line, entry.column)?;
+        self.show_quickfix_position(&entry);
+        Ok(())
+    }
+
+    /// Shows every quickfix entry in a picker and jumps to the one picked.
+    fn list_quickfix(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        if self.quickfix.is_empty() {
+            self.screen
+                .set_status_message("Quickfix list is empty".to_string(), Severity::Warning);
+            return Ok(());
+        }
+        let root = buffer.project_root().to_path_buf();
+        let items: Vec<String> = self
+            .quickfix
+            .entries()
+            .iter()
+            .map(|entry| entry.describe(&root))
+            .collect();
+        let mut picker = Picker::new(items.clone());
+        let Some(picked) = self.screen.pick("Quickfix: ", &mut picker)? else {
+            return Ok(());
+        };
+        let Some(entry) = items
+            .iter()
+            .position(|item| *item == picked)
+            .and_then(|idx| self.quickfix.select(idx))
+            .cloned()
+        else {
+            return Ok(());
+        };
+        self.open_location(buffer, &entry.path, entry.line, entry.column)?;
+        self.show_quickfix_position(&entry);
+        Ok(())
+    }
+
+    /** Runs the build command from the project root, showing a spinner until it's
+    done or Esc cancels it, and fills the quickfix list with the locations in its
+    output. Jumps to the first one if the build failed. */
+    fn build(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let root = buffer.project_root().to_path_buf();
+        let command = self.config.build_command.clone();
+        let mut build = match Build::spawn(&command, &root) {
+            Ok(build) => build,
+            Err(e) => {
+                self.screen.set_status_message(
+                    format!("Error: can't run {}: {}", command, e),
+                    Severity::Error,
+                );
+                return Ok(());
+            }
+        };
+        let (status, output) = loop {
+            match build.try_finish() {
+                Ok(Some((status, output))) => break (status, output.to_string()),
+                Ok(None) => {}
+                Err(e) => {
+                    self.screen.clear_progress()?;
+                    self.screen
+                        .set_status_message(format!("Error: {}", e), Severity::Error);
+                    return Ok(());
+                }
+            }
+            self.screen.show_progress(&command, None)?;
+            if let Some(Event::Key(KeyEvent {
+                code: KeyCode::Esc,
+                kind: KeyEventKind::Press,
+                ..
+            })) = self.event_handler.poll_event(BUILD_POLL_TIMEOUT)?
+            {
+                let _ = build.kill();
+                self.screen.clear_progress()?;
+                self.screen
+                    .set_status_message("Build cancelled".to_string(), Severity::Warning);
+                return Ok(());
+            }
+        };
+        self.screen.clear_progress()?;
+        self.quickfix = QuickfixList::parse(&output, &root);
+        let found = self.quickfix.len();
+        let locations = if found == 1 { "location" } else { "locations" };
+        if status.success() {
+            self.screen.set_status_message(
+                format!("Build succeeded, {} {} in quickfix list", found, locations),
+                Severity::Info,
+            );
+        } else if found == 0 {
+            self.screen
+                .set_status_message(format!("Build failed ({})", status), Severity::Error);
+        } else {
+            self.step_quickfix(buffer, true)?;
+        }
+        Ok(())
+    }
+
+    /** Inserts the next key as the character it stands for, skipping auto-indent,
+    overwrite mode and tab expansion, so e.g. a real tab or an escape can be typed. */
+    fn quoted_insert(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let key = self.screen.read_key("M-v")?;
+        // Line breaks would throw off the buffer's line endings, so Enter is what inserts those
+        let literal = literal_char(key)
+            .filter(|ch| !ch.is_control() || *ch == '\t' || caret_notation(*ch).is_some());
+        match literal {
+            Some(ch) => buffer.insert_char(ch),
+            None => self.screen.set_status_message(
+                "That key has no character to insert".to_string(),
+                Severity::Warning,
+            ),
+        }
+        Ok(())
+    }
+
+    /** Reads the keys of a sequence started with Ctrl+K, showing them as they're
+    typed, and runs the command bound to the sequence. Esc, a key nothing is bound
+    to, or waiting longer than `CHORD_TIMEOUT` for the next key cancels it. */
+    fn chord(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let mut keys = Vec::new();
+        let mut typed = "C-k".to_string();
+        loop {
+            let Some(key) = self.screen.read_key_within(&typed, CHORD_TIMEOUT)? else {
+                return Ok(());
+            };
+            if key.code == KeyCode::Esc {
+                return Ok(());
+            }
+            let Some(name) = key_name(&key) else {
+                typed.push_str(" ?");
+                break;
+            };
+            typed = format!("{} {}", typed, name);
+            keys.push(name);
+            match resolve(&keys) {
+                Resolution::Command(command) => return self.run_command(buffer, command),
+                Resolution::Pending => {}
+                Resolution::Unbound => break,
+            }
+        }
+        self.screen
+            .set_status_message(format!("{} isn't bound", typed), Severity::Warning);
+        Ok(())
+    }
+
+    /// Finishes the closing tag just started with `</`, in markup files.
+    fn close_tag(&mut self, buffer: &mut Buffer) {
+        let cursor = buffer.cursor_pos();
+        if !closes_tags(buffer.file_path().map(PathBuf::as_path))
+            || cursor < 2
+            || buffer.text_in_range(cursor - 2..cursor) != "</"
+        {
+            return;
+        }
+        if let Some(name) = unclosed_tag(&buffer.text_in_range(0..cursor)) {
+            buffer.insert_str(&format!("{}>", name));
+        }
+    }
+
+    fn toggle_zen(&mut self) {
+        let state = if self.screen.toggle_zen() {
+            "on"
+        } else {
+            "off"
+        };
+        self.screen.set_status_message(
+            format!("Distraction-free mode {} (Alt+Z to toggle)", state),
+            Severity::Info,
+        );
+    }
+
+    fn increment_number(&mut self, buffer: &mut Buffer, delta: i64) {
+        if !buffer.increment_number(delta) {
+            self.screen.set_status_message(
+                "No number under or after the cursor".to_string(),
+                Severity::Warning,
+            );
+        }
+    }
+
+    /// Deletes everything but the selection, e.g. to save a snippet of the file on its own.
+    fn keep_selection(&mut self, buffer: &mut Buffer) {
+        if !buffer.keep_selection() {
+            self.screen
+                .set_status_message("Nothing selected".to_string(), Severity::Warning);
+        }
+    }
+
+    /** Comments out the selected lines, or the cursor's line when nothing is
+    selected, or uncomments them, in the file's language. */
+    fn toggle_comment(&mut self, buffer: &mut Buffer) {
+        let Some(syntax) = comment_syntax(buffer.file_path().map(PathBuf::as_path)) else {
+            self.screen.set_status_message(
+                "No comment syntax known for this file".to_string(),
+                Severity::Warning,
+            );
+            return;
+        };
+        let lines = buffer.selected_lines();
+        buffer.toggle_comment(lines, syntax, self.config.block_comments);
+    }
+
+    /** Copies the selection, or the cursor's whole line when nothing is selected,
+    into a register, or the default one when `register` is `None`. Deletes it as well
+    when `cut` is set. */
+    fn yank_line(&mut self, buffer: &mut Buffer, register: Option<char>, cut: bool) {
+        let verb = if cut { "Cut" } else { "Yanked" };
+        if let Some(text) = buffer.selected_text() {
+            if cut {
+                buffer.delete_selection();
+            }
+            let count = text.chars().count();
+            self.registers.set(register, text);
+            self.screen
+                .set_status_message(format!("{} {} chars", verb, count), Severity::Info);
+            return;
+        }
+        let row = buffer.cursor_row();
+        let text = if cut {
+            buffer.remove_lines(row..row + 1)
+        } else {
+            buffer.lines_text(row..row + 1)
+        };
+        self.registers.set(register, text);
+        let message = match register {
+            Some(register) => format!("{} 1 line into \"{}", verb, register),
+            None => format!("{} 1 line", verb),
+        };
+        self.screen.set_status_message(message, Severity::Info);
+    }
+
+    /** Pastes a register's text, linewise text below the cursor's line and anything
+    else at the cursor. With `reindent`, its indentation is adjusted to fit there. */
+    fn paste(&mut self, buffer: &mut Buffer, register: Option<char>, reindent: bool) {
+        let Some(text) = self.registers.get(register) else {
+            self.screen
+                .set_status_message("Register is empty".to_string(), Severity::Warning);
+            return;
+        };
+        if reindent {
+            let style = buffer
+                .indent_style()
+                .unwrap_or_else(|| self.config.indent_style());
+            buffer.insert_reindented(text, style);
+        } else if text.ends_with('\n') {
+            buffer.insert_lines_below(text);
+        } else {
+            buffer.insert_str(text);
+        }
+    }
+
+    /** Pastes the default register at the cursor, leaving the cursor just past it.
+    Unlike `paste`, linewise text goes in where the cursor is rather than below. */
+    fn paste_at_cursor(&mut self, buffer: &mut Buffer) {
+        match self.registers.get(None) {
+            Some(text) => {
+                buffer.delete_selection();
+                buffer.insert_str(text);
+            }
+            None => self
+                .screen
+                .set_status_message("Nothing to paste".to_string(), Severity::Warning),
+        }
+    }
+
+    /** Copies the selection, or the cursor's line, to the system clipboard. Without one, e.g. over SSH,
+    it goes in the default register instead. */
+    fn copy_to_system(&mut self, buffer: &mut Buffer) {
+        let row = buffer.cursor_row();
+        let text = buffer
+            .selected_text()
+            .unwrap_or_else(|| buffer.lines_text(row..row + 1));
+        let copied = self
+            .clipboard
+            .as_mut()
+            .ok_or_else(|| "no system clipboard".to_string())
+            .and_then(|clipboard| clipboard.copy(&text).map_err(|e| e.to_string()));
+        match copied {
+            Ok(()) => self
+                .screen
+                .set_status_message("Copied to the clipboard".to_string(), Severity::Info),
+            Err(e) => {
+                self.registers.set(None, text);
+                self.screen.set_status_message(
+                    format!("Copied to the register instead ({})", e),
+                    Severity::Warning,
+                );
+            }
+        }
+    }
+
+    /** Pastes the system clipboard at the cursor, or the default register when
+    there's no clipboard to read. */
+    fn paste_from_system(&mut self, buffer: &mut Buffer) {
+        let pasted = self
+            .clipboard
+            .as_mut()
+            .ok_or_else(|| "no system clipboard".to_string())
+            .and_then(|clipboard| clipboard.paste().map_err(|e| e.to_string()));
+        match pasted {
+            Ok(text) => buffer.insert_str(&buffer.with_line_endings(&text)),
+            Err(e) => {
+                self.screen.set_status_message(
+                    format!("Pasting from the register instead ({})", e),
+                    Severity::Warning,
+                );
+                self.paste_at_cursor(buffer);
+            }
+        }
+    }
+
+    /// Shows what every register holds in a picker and pastes the one picked.
+    fn show_registers(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let filled = self.registers.filled();
+        if filled.is_empty() {
+            self.screen
+                .set_status_message("All registers are empty".to_string(), Severity::Warning);
+            return Ok(());
+        }
+        let items: Vec<String> = filled
+            .iter()
+            .map(|(name, text)| format!("\"{}  {}", name, text.escape_debug()))
+            .collect();
+        let mut picker = Picker::new(items);
+        let picked = self.screen.pick("Registers: ", &mut picker)?;
+        if let Some(name) = picked.and_then(|item| item.chars().nth(1)) {
+            self.paste(buffer, Some(name), false);
+        }
+        Ok(())
+    }
+
+    fn show_quickfix_position(&mut self, entry: &QuickfixEntry) {
+        let current = self.quickfix.current_idx().map_or(0, |idx| idx + 1);
+        self.screen.set_status_message(
+            format!("({} of {}) {}", current, self.quickfix.len(), entry.message),
+            Severity::Info,
+        );
+    }
+
+    /// Applies the `.editorconfig` properties that match the buffer's file.
+    fn apply_editorconfig(&mut self, buffer: &mut Buffer) {
+        let Some(path) = buffer.file_path() else {
+            return;
+        };
+        let (properties, warnings) = editorconfig::load(path);
+        if let Some(warning) = warnings.first() {
+            self.screen
+                .set_status_message(format!("Warning: {}", warning), Severity::Warning);
+        }
+        if let Some(use_tabs) = properties.use_tabs {
+            let style = if use_tabs {
+                IndentStyle::Tabs
+            } else {
+                let width = properties
+                    .indent_size
+                    .or(properties.tab_width)
+                    .unwrap_or(self.config.indent_width);
+                IndentStyle::Spaces(width)
+            };
+            buffer.set_indent_style(style);
+        } else if let (Some(width), Some(IndentStyle::Spaces(_))) =
+            (properties.indent_size, buffer.indent_style())
+        {
+            buffer.set_indent_style(IndentStyle::Spaces(width));
+        }
+        if let Some(width) = properties.tab_width.or(properties.indent_size) {
+            buffer.set_tab_width(width);
+        }
+        if let Some(ending) = properties.end_of_line {
+            buffer.set_line_ending(ending);
+        }
+        if let Some(trim) = properties.trim_trailing_whitespace {
+            buffer.set_trim_trailing_whitespace(trim);
+        }
+        if let Some(insert) = properties.insert_final_newline {
+            buffer.set_insert_final_newline(insert);
+        }
+    }
+
+    /// Applies the settings from the buffer's modeline, if it has one, to that buffer only.
+    fn apply_modeline(&self, buffer: &mut Buffer) {
+        let Some(modeline) = find_modeline(buffer) else {
+            return;
+        };
+        if let Some(width) = modeline.tab_width {
+            buffer.set_tab_width(width);
+        }
+        if modeline.expand_tab.is_none() && modeline.indent_width.is_none() {
+            return;
+        }
+        let current = buffer
+            .indent_style()
+            .unwrap_or_else(|| self.config.indent_style());
+        let expand_tab = modeline
+            .expand_tab
+            .unwrap_or(matches!(current, IndentStyle::Spaces(_)));
+        let style = if expand_tab {
+            let width = match current {
+                IndentStyle::Spaces(width) => width,
+                IndentStyle::Tabs => self.config.indent_width,
+            };
+            IndentStyle::Spaces(modeline.indent_width.unwrap_or(width))
+        } else {
+            IndentStyle::Tabs
+        };
+        buffer.set_indent_style(style);
+    }
+
+    /// Expands `~` and environment variables in a path, warning about unknown variables.
+    fn expand_path(&mut self, path: &str) -> String {
+        let (expanded, unknown) = expand_path(path);
+        if !unknown.is_empty() {
+            let names: Vec<String> = unknown.iter().map(|name| format!("${}", name)).collect();
+            self.screen.set_status_message(
+                format!("Warning: unknown variables {}", names.join(", ")),
+                Severity::Warning,
+            );
+        }
+        expanded
+    }
+
+    /// Saves the buffer, asking for a file name first if it doesn't have one.
+    fn save(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        if buffer.file_path().is_none() {
+            match self.screen.prompt_with(
+                PromptKind::Path,
+                "Save as: ",
+                "",
+                &mut PathCompletion::default(),
+            )? {
+                Some(path) if !path.is_empty() => {
+                    let path = self.expand_path(&path);
+                    buffer.set_file_path(PathBuf::from(path));
+                }
+                _ => return Ok(()),
+            }
+        }
+        if buffer.changed_on_disk()
+            && !self
+                .screen
+                .confirm("File changed on disk since you opened it. Overwrite? (y/n)")?
+        {
+            self.screen
+                .set_status_message("Not saved".to_string(), Severity::Warning);
+            return Ok(());
+        }
+        match buffer.save(self.config.backup) {
+            Ok(message) => self.screen.set_status_message(message, Severity::Info),
+            Err(e) => self
+                .screen
+                .set_status_message(format!("Error: {}", e), Severity::Error),
+        }
+        Ok(())
+    }
+
+    /// Jumps to the next (or previous) match of the last search query.
+    fn repeat_search(&mut self, buffer: &mut Buffer, forward: bool) {
+        let query = match &self.last_search {
+            Some(query) => query,
+            None => {
+                self.screen
+                    .set_status_message("No previous search".to_string(), Severity::Warning);
+                return;
+            }
+        };
+        let cursor_pos = buffer.cursor_pos();
+        let found = if forward {
+            buffer.find_next(query, cursor_pos + 1, self.search_options)
+        } else {
+            buffer.find_prev(query, cursor_pos, self.search_options)
+        };
+        match found {
+            Some(pos) => buffer.set_cursor_pos(pos),
+            None => self
+                .screen
+                .set_status_message(format!("Pattern not found: {}", query), Severity::Warning),
+        }
+    }
+
+    fn count_word_at_cursor(&mut self, buffer: &Buffer) {
+        match buffer.word_at_cursor() {
+            Some(word) => {
+                let options = SearchOptions {
+                    whole_word: self.config.count_whole_word,
+                    ..SearchOptions::default()
+                };
+                let count = buffer.count_occurrences(&word, options);
+                let times = if count == 1 { "time" } else { "times" };
+                self.screen.set_status_message(
+                    format!("'{}' appears {} {}", word, count, times),
+                    Severity::Info,
+                );
+            }
+            None => self
+                .screen
+                .set_status_message("No word under cursor".to_string(), Severity::Warning),
+        }
+    }
+
+    /// Runs a command typed at the `:` prompt.
+    fn run_command(&mut self, buffer: &mut Buffer, command: &str) -> crossterm::Result<()> {
+        let mut words = command.split_whitespace();
+        match words.next() {
+            Some("set") => {
+                // A value can have spaces in it, so `name=value` takes the rest of the line
+                let rest = command.trim_start()["set".len()..].trim();
+                let settings: Vec<&str> = if rest.contains('=') {
+                    vec![rest]
+                } else {
+                    words.collect()
+                };
+                if settings.is_empty() {
+                    self.show_options();
+                }
+                for setting in settings {
+                    match self.config.set(setting) {
+                        Ok(state) => self.screen.set_status_message(state, Severity::Info),
+                        Err(e) => {
+                            self.screen
+                                .set_status_message(format!("Error: {}", e), Severity::Error);
+                            break;
+                        }
+                    }
+                }
+                self.apply_config(buffer)?;
+            }
+            Some("retab") => {
+                let width = match buffer.indent_style() {
+                    Some(IndentStyle::Spaces(width)) => width,
+                    _ => self.config.indent_width,
+                };
+                let changed = match words.next() {
+                    Some("spaces") => buffer.tabs_to_spaces(width),
+                    Some("tabs") => buffer.spaces_to_tabs(width),
+                    _ => {
+                        self.screen.set_status_message(
+                            "Usage: retab spaces|tabs".to_string(),
+                            Severity::Warning,
+                        );
+                        return Ok(());
+                    }
+                };
+                self.screen
+                    .set_status_message(format!("Retabbed {} lines", changed), Severity::Info);
+            }
+            Some("fold") => {
+                let Some(level) = words.next().and_then(|level| level.parse().ok()) else {
+                    self.screen
+                        .set_status_message("Usage: fold <level>".to_string(), Severity::Warning);
+                    return Ok(());
+                };
+                let width = match buffer.indent_style() {
+                    Some(IndentStyle::Spaces(width)) => width,
+                    _ => self.config.indent_width,
+                };
+                let folds = buffer.fold_to_level(level, width);
+                self.screen
+                    .set_status_message(format!("Folded {} blocks", folds), Severity::Info);
+            }
+            Some("unfold") => buffer.unfold_all(),
+            Some("grep") => {
+                let query: Vec<&str> = words.collect();
+                let query = (!query.is_empty()).then(|| query.join(" "));
+                self.grep(buffer, query)?;
+            }
+            Some("build") => {
+                let command: Vec<&str> = words.collect();
+                if !command.is_empty() {
+                    self.config.build_command = command.join(" ");
+                }
+                self.build(buffer)?;
+            }
+            Some(command @ ("yank" | "cut" | "paste" | "rpaste")) => {
+                let register = match words.next() {
+                    Some(name) => match Registers::parse_name(name) {
+                        Some(register) => Some(register),
+                        None => {
+                            self.screen.set_status_message(
+                                format!("Error: Invalid register: {}", name),
+                                Severity::Error,
+                            );
+                            return Ok(());
+                        }
+                    },
+                    None => None,
+                };
+                match command {
+                    "yank" => self.yank_line(buffer, register, false),
+                    "cut" => self.yank_line(buffer, register, true),
+                    "paste" => self.paste(buffer, register, false),
+                    _ => self.paste(buffer, register, true),
+                }
+            }
+            Some("keep") => self.keep_selection(buffer),
+            Some("duplicate") => {
+                let lines = buffer.selected_lines();
+                buffer.duplicate_lines(lines);
+            }
+            Some(command @ ("increment" | "decrement")) => {
+                let Some(count) = words
+                    .next()
+                    .map_or(Some(1), |count| count.parse::<i64>().ok())
+                else {
+                    self.screen.set_status_message(
+                        format!("Usage: {} [count]", command),
+                        Severity::Warning,
+                    );
+                    return Ok(());
+                };
+                let delta = if command == "increment" {
+                    count
+                } else {
+                    -count
+                };
+                self.increment_number(buffer, delta);
+            }
+            Some("strip") => {
+                let changed = buffer.strip_trailing_whitespace();
+                let lines = if changed == 1 { "line" } else { "lines" };
+                self.screen.set_status_message(
+                    format!("Stripped trailing whitespace from {} {}", changed, lines),
+                    Severity::Info,
+                );
+            }
+            Some("zen") => self.toggle_zen(),
+            Some("reload") => self.load_config(buffer, true)?,
+            Some("revert") => self.revert(buffer)?,
+            Some("comment") => self.toggle_comment(buffer),
+            Some("registers") => self.show_registers(buffer)?,
+            Some("cnext") => self.step_quickfix(buffer, true)?,
+            Some("cprev") => self.step_quickfix(buffer, false)?,
+            Some("clist") => self.list_quickfix(buffer)?,
+            Some("unicode") => {
+                let code = match words.next() {
+                    Some(code) => code.to_string(),
+                    None => match self
+                        .screen
+                        .prompt(PromptKind::CodePoint, "Code point: U+")?
+                    {
+                        Some(code) => code,
+                        None => return Ok(()),
+                    },
+                };
+                match parse_code_point(&code) {
+                    Ok(ch) => buffer.insert_str(&ch.to_string()),
+                    Err(e) => self
+                        .screen
+                        .set_status_message(format!("Error: {}", e), Severity::Error),
+                }
+            }
+            Some("backup") => {
+                if let Some(mode) = words.next() {
+                    let kept = match words.next().map(str::parse) {
+                        Some(Ok(kept)) => kept,
+                        Some(Err(_)) => 0,
+                        None => DEFAULT_BACKUPS_KEPT,
+                    };
+                    match Backup::parse(mode, kept) {
+                        Some(backup) if kept > 0 => self.config.backup = backup,
+                        _ => {
+                            self.screen.set_status_message(
+                                "Usage: backup [off|simple|numbered [count]]".to_string(),
+                                Severity::Warning,
+                            );
+                            return Ok(());
+                        }
+                    }
+                }
+                self.screen
+                    .set_status_message(format!("Backups: {}", self.config.backup), Severity::Info);
+            }
+            Some("tabstops") => {
+                let stops = match words.next() {
+                    Some(list) => match TabStops::parse(list) {
+                        Some(stops) => Some(stops),
+                        None => {
+                            self.screen.set_status_message(
+                                "Usage: tabstops [column,column,...] in increasing order"
+                                    .to_string(),
+                                Severity::Warning,
+                            );
+                            return Ok(());
+                        }
+                    },
+                    None => None,
+                };
+                self.config.tab_stops = stops.clone();
+                buffer.set_tab_stops(stops);
+                self.screen.set_status_message(
+                    format!("Tabs stop at {}", buffer.tab_stops()),
+                    Severity::Info,
+                );
+            }
+            Some("wordchars") => {
+                let extra = words.next().unwrap_or("");
+                self.config.word_chars = WordChars::new(extra);
+                buffer.set_word_chars(self.config.word_chars.clone());
+                self.screen.set_status_message(
+                    format!("Words are letters, digits and \"{}\"", extra),
+                    Severity::Info,
+                );
+            }
+            // Replacing is limited to the selection when there is one
+            Some("replace") => {
+                let scope = buffer.selection_range().map(|(start, end)| start..end);
+                self.replace_all(buffer, scope)?
+            }
+            Some(name) => self
+                .screen
+                .set_status_message(format!("Error: Unknown command: {}", name), Severity::Error),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /** Prompts for a query and replacement and replaces every match, either in
+    the whole buffer or only inside `scope` when one is given. */
+    fn replace_all(
+        &mut self,
+        buffer: &mut Buffer,
+        scope: Option<Range<usize>>,
+    ) -> crossterm::Result<()> {
+        let label = if scope.is_some() {
+            "Replace in selection"
+        } else {
+            "Replace"
+        };
+        let last_search = self.last_search.clone().unwrap_or_default();
+        let query = match self.screen.prompt_with(
+            PromptKind::Search,
+            &format!("{}: ", label),
+            &last_search,
+            &mut self.search_options,
+        )? {
+            Some(query) if !query.is_empty() => query,
+            _ => return Ok(()),
+        };
+        self.last_search = Some(query.clone());
+        self.screen
+            .set_search_highlight(&query, self.search_options, None);
+        self.screen.display_buffer(buffer, &self.config)?;
+        let replacement = self.screen.prompt(
+            PromptKind::Replacement,
+            &format!("{} {} with: ", label, query),
+        )?;
+        self.screen.clear_search_highlight();
+        let replacement = match replacement {
+            Some(replacement) => replacement,
+            None => return Ok(()),
+        };
+        let screen = &mut self.screen;
+        let options = self.search_options;
+        let count = buffer.replace_all(&query, &replacement, options, scope, |done, total| {
+            let _ = screen.show_progress("Replacing", Some((done as u64, total as u64)));
+        });
+        self.screen.clear_progress()?;
+        self.screen
+            .set_status_message(format!("Replaced {} occurrences", count), Severity::Info);
+        Ok(())
+    }
+
+    /** Prompts for a query and replacement, then steps through the matches from the
+    cursor onwards, wrapping around once, and asks whether to replace each one: y replaces
+    it, n skips it, a replaces it and every match after it, and Esc or q stops. */
+    fn query_replace(&mut self, buffer: &mut Buffer) -> crossterm::Result<()> {
+        let last_search = self.last_search.clone().unwrap_or_default();
+        let query = match self.screen.prompt_with(
+            PromptKind::Search,
+            "Query replace: ",
+            &last_search,
+            &mut self.search_options,
+        )? {
+            Some(query) if !query.is_empty() => query,
+            _ => return Ok(()),
+        };
+        self.last_search = Some(query.clone());
+        let replacement = match self.screen.prompt(
+            PromptKind::Replacement,
+            &format!("Query replace {} with: ", query),
+        )? {
+            Some(replacement) => replacement,
+            None => return Ok(()),
+        };
+        let options = self.search_options;
+        let mut from = buffer.cursor_pos();
+        // Where the search began, kept in step with replacements made before it
+        let mut stop = from;
+        let mut wrapped = false;
+        let mut replace_rest = false;
+        let mut count = 0;
+        while let Some(start) = buffer.find_next(&query, from, options) {
+            if start < from {
+                if wrapped {
+                    break;
+                }
+                wrapped = true;
+            }
+            if wrapped && start >= stop {
+                break;
+            }
+            buffer.set_cursor_pos(start);
+            if !replace_rest {
+                self.screen
+                    .set_search_highlight(&query, options, Some(start));
+                self.screen.display_buffer(buffer, &self.config)?;
+                let key = self
+                    .screen
+                    .read_key(&format!("Replace with {}? (y/n/a) ", replacement))?;
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') => {}
+                    KeyCode::Char('a') | KeyCode::Char('A') => replace_rest = true,
+                    KeyCode::Char('n') | KeyCode::Char('N') => {
+                        from = start + 1;
+                        continue;
+                    }
+                    KeyCode::Esc | KeyCode::Char('q') => break,
+                    _ => continue,
+                }
+            }
+            let len_before = buffer.len_chars();
+            buffer.replace_next(&query, &replacement, options);
+            if start < stop {
+                stop =
+                    stop.saturating_add_signed(buffer.len_chars() as isize - len_before as isize);
+            }
+            from = buffer.cursor_pos();
+            count += 1;
+        }
+        self.screen.clear_search_highlight();
+        self.screen
+            .set_status_message(format!("Replaced {} occurrences", count), Severity::Info);
+        Ok(())
+    }
+
+    fn show_options(&mut self) {
+        let options: Vec<String> = Config::OPTIONS
+            .iter()
+            .filter_map(|option| {
+                let state = if self.config.option(option.name)? {
+                    "on"
+                } else {
+                    "off"
+                };
+                Some(format!("{}={}", option.name, state))
+            })
+            .chain(Config::VALUE_OPTIONS.iter().filter_map(|option| {
+                Some(format!(
+                    "{}={}",
+                    option.name,
+                    self.config.value(option.name)?
+                ))
+            }))
+            .collect();
+        self.screen
+            .set_status_message(options.join(" "), Severity::Info);
+    }
+
+    fn process_event(&mut self, buffer: &mut Buffer, event: Event) -> crossterm::Result<bool> {
+        match event {
+            Event::Key(key_event) => {
+                if key_event.kind == KeyEventKind::Press {
+                    self.dirty |= self.screen.dismiss_error_message();
+                }
+                let keep_running = self.process_keypress(buffer, key_event)?;
+                buffer.end_undo_step();
+                return Ok(keep_running);
             }
             Event::Resize(width, height) => {
-                self.screen.update_window_size(width, height)?;
+                self.screen
+                    .update_window_size(width, height, buffer, &self.config)?;
+                self.dirty = true;
+            }
+            // Inserted in one go, so auto-indent doesn't pile up on each pasted line
+            Event::Paste(text) => {
+                buffer.delete_selection();
+                let text = buffer.with_line_endings(&text);
+                if self.config.reindent_paste {
+                    let style = buffer
+                        .indent_style()
+                        .unwrap_or_else(|| self.config.indent_style());
+                    buffer.insert_reindented(&text, style);
+                } else {
+                    buffer.insert_str(&text);
+                }
+                buffer.end_undo_step();
+                self.dirty = true;
             }
             _ => {}
         }
         Ok(true)
     }
 
+    /** Redraws the screen if something marked the editor dirty, then handles the
+    next event, or runs the idle tasks if none arrives in time.
+    Returns `false` once the editor should quit. */
     fn run(&mut self, buffer: &mut Buffer) -> crossterm::Result<bool> {
-        self.screen.display_buffer(&buffer)?;
-        self.process_events(buffer)
+        if self.dirty {
+            self.screen.display_buffer(buffer, &self.config)?;
+            self.dirty = false;
+        }
+        match self.event_handler.poll_event(POLL_TIMEOUT)? {
+            Some(event) => {
+                if !self.process_event(buffer, event)? {
+                    return Ok(false);
+                }
+            }
+            None => {
+                for action in self.idle_tasks.due() {
+                    self.dirty |= action(self, buffer)?;
+                }
+            }
+        }
+        Ok(true)
     }
 }
 
 fn main() -> crossterm::Result<()> {
+    if !stdout().is_tty() {
+        eprintln!("stte: standard output isn't a terminal");
+        process::exit(1);
+    }
+    // Enter the alternate screen buffer, or keep to the main one if there isn't one
+    let alternate_screen =
+        supports_alternate_screen() && execute!(stdout(), EnterAlternateScreen).is_ok();
+    if !alternate_screen {
+        // Push what's on screen into the scrollback, so drawing doesn't cover it
+        let (_, height) = terminal::size()?;
+        print!("{}", "\n".repeat(height as usize));
+    }
     // When this variable goes out of scope the drop method is ran
-    let _clean_up: CleanUp = CleanUp;
-    // Enter the alternate screen buffer
-    execute!(stdout(), EnterAlternateScreen)?;
-    terminal::enable_raw_mode()?;
+    let clean_up = CleanUp { alternate_screen };
+    if let Err(e) = terminal::enable_raw_mode() {
+        drop(clean_up);
+        eprintln!("stte: can't put the terminal in raw mode: {}", e);
+        process::exit(1);
+    }
+    execute!(stdout(), EnableBracketedPaste)?;
     let mut editor: TextEditor = TextEditor::new();
+    // Loaded before any file is opened, since opening one depends on the settings
+    editor.load_config(&mut Buffer::new(None), false)?;
     let args: Vec<String> = env::args().collect();
-    let mut buffer: Buffer = if args.len() > 1 {
-        let path: &String = &args[1];
-        match Buffer::from_path(&path) {
-            Ok(buffer) => buffer,
-            Err(error) => {
-                editor.screen.set_status_message(error.to_string());
-                Buffer::new(Some(PathBuf::from(&path))) // Create a buffer if there's an error but a path is still provided
-            }
-        }
+    let path = args.get(1).map(|arg| editor.expand_path(arg));
+    // A directory opens the file finder there instead, like `stte .` for a project
+    let directory = path
+        .as_ref()
+        .map(PathBuf::from)
+        .filter(|path| path.is_dir());
+    let mut buffer: Buffer = if let Some(path) = path.filter(|_| directory.is_none()) {
+        editor.open_buffer(&path)?
     } else {
-        Buffer::new(None) // Create an empty buffer if no file is specified
+        // Create an empty buffer if no file is specified
+        let mut buffer = Buffer::new(None);
+        buffer.set_word_chars(editor.config.word_chars.clone());
+        buffer
     };
     // Clear terminal screen on first run
     editor.screen.clear()?;
+    editor.screen.apply_cursor_style(&editor.config)?;
+    if let Some(directory) = directory {
+        editor.find_file_in(&mut buffer, directory)?;
+    }
     while editor.run(&mut buffer)? {}
     Ok(())
 }
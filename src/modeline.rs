@@ -0,0 +1,47 @@
+use crate::buffer::Buffer;
+
+/// How many lines at the top and bottom of a file are searched for a modeline.
+const MODELINE_LINES: usize = 5;
+
+/** Settings a file asks for in a comment line like `# stte: tabwidth=2 expandtab`.
+Only the settings the line mentions are `Some`. */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Modeline {
+    pub tab_width: Option<usize>,
+    pub expand_tab: Option<bool>,
+    pub indent_width: Option<usize>,
+}
+
+/** Looks for a modeline near the top or bottom of the buffer. The first line
+containing `stte:` wins. Directives that are unknown or malformed are skipped. */
+pub fn find_modeline(buffer: &Buffer) -> Option<Modeline> {
+    let line_count = buffer.line_count();
+    let top = 0..MODELINE_LINES.min(line_count);
+    let bottom = line_count.saturating_sub(MODELINE_LINES).max(top.end)..line_count;
+    top.chain(bottom)
+        .find_map(|idx| parse_modeline(&buffer.get_line(idx).to_string()))
+}
+
+fn parse_modeline(line: &str) -> Option<Modeline> {
+    let (_, directives) = line.split_once("stte:")?;
+    let mut modeline = Modeline::default();
+    for directive in directives.split_whitespace() {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (directive, None),
+        };
+        let number = value
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|number| *number > 0);
+        match (name, value) {
+            ("tabwidth" | "ts", Some(_)) => modeline.tab_width = number.or(modeline.tab_width),
+            ("indentwidth" | "sw", Some(_)) => {
+                modeline.indent_width = number.or(modeline.indent_width)
+            }
+            ("expandtab" | "et", None) => modeline.expand_tab = Some(true),
+            ("noexpandtab" | "noet", None) => modeline.expand_tab = Some(false),
+            _ => {}
+        }
+    }
+    Some(modeline)
+}
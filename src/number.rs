@@ -0,0 +1,68 @@
+use std::ops::Range;
+
+/** Finds the number under or after char index `column` in `line`, returning its
+char range. Numbers are decimal, with a `-` in front counting as a sign unless
+it follows a letter or digit, or hex with a `0x` prefix. */
+pub fn find_number(line: &str, column: usize) -> Option<Range<usize>> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut idx = 0;
+    while idx < chars.len() {
+        let start = idx;
+        let is_hex = chars[idx] == '0'
+            && matches!(chars.get(idx + 1), Some('x' | 'X'))
+            && chars.get(idx + 2).is_some_and(char::is_ascii_hexdigit);
+        let is_negative = chars[idx] == '-'
+            && chars.get(idx + 1).is_some_and(char::is_ascii_digit)
+            && (idx == 0 || !chars[idx - 1].is_alphanumeric());
+        let digits: fn(&char) -> bool = if is_hex {
+            idx += 2;
+            char::is_ascii_hexdigit
+        } else if is_negative || chars[idx].is_ascii_digit() {
+            if is_negative {
+                idx += 1;
+            }
+            char::is_ascii_digit
+        } else {
+            idx += 1;
+            continue;
+        };
+        while chars.get(idx).is_some_and(digits) {
+            idx += 1;
+        }
+        if idx > column {
+            return Some(start..idx);
+        }
+    }
+    None
+}
+
+/** Adds `delta` to a number as `find_number` finds them, keeping its format: the
+number of digits when it has leading zeros, and the case of hex digits. Hex numbers
+wrap around rather than going negative. Returns `None` if it doesn't fit in 64 bits. */
+pub fn add_to_number(number: &str, delta: i64) -> Option<String> {
+    if let Some(hex) = number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        let value = u64::from_str_radix(hex, 16)
+            .ok()?
+            .wrapping_add_signed(delta);
+        let width = hex.len();
+        let formatted = if hex.chars().any(|ch| ch.is_ascii_uppercase()) {
+            format!("{:0width$X}", value)
+        } else {
+            format!("{:0width$x}", value)
+        };
+        return Some(format!("{}{}", &number[..2], formatted));
+    }
+    let value = number.parse::<i64>().ok()?.checked_add(delta)?;
+    let digits = number.trim_start_matches('-');
+    // Only pad numbers that were padded to begin with
+    let width = if digits.len() > 1 && digits.starts_with('0') {
+        digits.len()
+    } else {
+        0
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    Some(format!("{}{:0width$}", sign, value.unsigned_abs()))
+}
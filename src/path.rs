@@ -0,0 +1,224 @@
+use crate::config::PathDisplay;
+use crate::screen::PromptHandler;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use unicode_width::UnicodeWidthStr;
+
+/** Expands a leading `~` or `~user` to a home directory and `$VAR`/`${VAR}` to
+the variable's value. Unknown variables expand to nothing, and their names are
+returned alongside the expanded path so the caller can warn about them. */
+pub fn expand_path(input: &str) -> (String, Vec<String>) {
+    let mut unknown = Vec::new();
+    let mut expanded = String::new();
+    let mut rest = input;
+
+    if let Some(after_tilde) = input.strip_prefix('~') {
+        let end = after_tilde.find('/').unwrap_or(after_tilde.len());
+        let user = &after_tilde[..end];
+        let home = if user.is_empty() {
+            env::var("HOME").ok()
+        } else {
+            home_dir_of(user)
+        };
+        if let Some(home) = home {
+            expanded.push_str(&home);
+            rest = &after_tilde[end..];
+        }
+    }
+
+    let mut chars = rest.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            expanded.push(ch);
+            continue;
+        }
+        let name: String = if chars.peek() == Some(&'{') {
+            chars.next();
+            chars.by_ref().take_while(|ch| *ch != '}').collect()
+        } else {
+            let mut name = String::new();
+            while let Some(ch) = chars.next_if(|ch| ch.is_alphanumeric() || *ch == '_') {
+                name.push(ch);
+            }
+            name
+        };
+        if name.is_empty() {
+            // A lone `$` isn't a variable
+            expanded.push('$');
+            continue;
+        }
+        match env::var(&name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) => unknown.push(name),
+        }
+    }
+    (expanded, unknown)
+}
+
+/// Looks up a user's home directory in `/etc/passwd`.
+#[cfg(unix)]
+fn home_dir_of(user: &str) -> Option<String> {
+    let passwd = fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        // name:password:uid:gid:gecos:home:shell
+        let mut fields = line.split(':');
+        if fields.next()? != user {
+            return None;
+        }
+        fields.nth(4).map(str::to_string)
+    })
+}
+
+#[cfg(not(unix))]
+fn home_dir_of(_user: &str) -> Option<String> {
+    None
+}
+
+/// Files and directories that mark the top of a project.
+const PROJECT_MARKERS: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    "Cargo.toml",
+    "package.json",
+    "go.mod",
+    "pyproject.toml",
+    "Makefile",
+];
+
+/** Walks up from the directory holding `file` to the nearest one containing
+a project marker like `.git` or `Cargo.toml`. Falls back to the working directory
+when there's no marker, or no file. */
+pub fn find_project_root(file: Option<&Path>) -> PathBuf {
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let Some(file) = file.and_then(|file| std::path::absolute(file).ok()) else {
+        return cwd;
+    };
+    file.ancestors()
+        .skip(1)
+        .find(|dir| {
+            PROJECT_MARKERS
+                .iter()
+                .any(|marker| dir.join(marker).exists())
+        })
+        .map_or(cwd, Path::to_path_buf)
+}
+
+/// Formats `path` for display as `mode` asks. `root` is the project root the path is in.
+pub fn display_path(path: &Path, mode: PathDisplay, root: &Path) -> String {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let shown = match mode {
+        PathDisplay::Name => absolute.file_name().map(Path::new).unwrap_or(path),
+        PathDisplay::Relative => env::current_dir()
+            .ok()
+            .and_then(|cwd| absolute.strip_prefix(cwd).ok())
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .unwrap_or(&absolute),
+        PathDisplay::Project => absolute.strip_prefix(root).unwrap_or(&absolute),
+        PathDisplay::Absolute => &absolute,
+    };
+    shown.to_string_lossy().into_owned()
+}
+
+/** Shortens `path` to fit in `width` columns by replacing directories in the middle
+with `…`, keeping the first component and as many of the last ones as fit,
+e.g. `/home/…/src/main.rs`. The file name is never shortened, even if it doesn't fit. */
+pub fn elide_path(path: &str, width: usize) -> String {
+    if path.width() <= width {
+        return path.to_string();
+    }
+    let components: Vec<&str> = path.split('/').collect();
+    let Some((file_name, dirs)) = components.split_last() else {
+        return path.to_string();
+    };
+    // For absolute paths the first component is the one after the root
+    let (first, middle) = match dirs {
+        ["", top, rest @ ..] => (format!("/{}", top), rest),
+        [first, rest @ ..] => (first.to_string(), rest),
+        [] => return path.to_string(),
+    };
+    let mut kept = file_name.to_string();
+    for dir in middle.iter().rev() {
+        let longer = format!("{}/{}", dir, kept);
+        if format!("{}/…/{}", first, longer).width() > width {
+            break;
+        }
+        kept = longer;
+    }
+    let elided = format!("{}/…/{}", first, kept);
+    if elided.width() <= width {
+        elided
+    } else if format!("…/{}", file_name).width() <= width {
+        format!("…/{}", file_name)
+    } else {
+        file_name.to_string()
+    }
+}
+
+/** Completes file paths typed into a prompt when Tab is pressed.
+Pressing Tab again cycles through the other matching entries. */
+#[derive(Default)]
+pub struct PathCompletion {
+    candidates: Vec<String>,
+    idx: usize,
+}
+
+impl PathCompletion {
+    /// Lists the entries matching the last component of `input`, keeping the directory part as typed.
+    fn candidates_for(input: &str) -> Vec<String> {
+        let (dir, prefix) = match input.rfind('/') {
+            Some(idx) => input.split_at(idx + 1),
+            None => ("", input),
+        };
+        let dir_path = if dir.is_empty() {
+            PathBuf::from(".")
+        } else {
+            PathBuf::from(expand_path(dir).0)
+        };
+        let entries = match fs::read_dir(dir_path) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut candidates: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                // Hidden entries are only offered once a `.` has been typed
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                let separator = if entry.path().is_dir() { "/" } else { "" };
+                Some(format!("{}{}{}", dir, name, separator))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+impl PromptHandler for PathCompletion {
+    fn on_key(&mut self, key_event: &KeyEvent, input: &mut String) -> bool {
+        if key_event.kind != KeyEventKind::Press {
+            return false;
+        }
+        if key_event.code != KeyCode::Tab || key_event.modifiers != KeyModifiers::NONE {
+            // Any other key starts a new completion
+            self.candidates.clear();
+            return false;
+        }
+        // With a single candidate, Tab again completes inside it, e.g. after a directory
+        if self.candidates.len() > 1 && self.candidates.get(self.idx) == Some(input) {
+            self.idx = (self.idx + 1) % self.candidates.len();
+        } else {
+            self.candidates = Self::candidates_for(input);
+            self.idx = 0;
+        }
+        if let Some(candidate) = self.candidates.get(self.idx) {
+            *input = candidate.clone();
+        }
+        true
+    }
+}
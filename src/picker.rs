@@ -0,0 +1,127 @@
+use crate::project::fuzzy_score;
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+/// Most items taken from the channel per redraw, so a fast producer can't stall the UI.
+const RECEIVE_BATCH: usize = 2_000;
+
+/** A list to choose from, filtered fuzzily by what's been typed. Items can keep
+arriving from a background task while the list is shown. */
+pub struct Picker {
+    items: Vec<String>,
+    incoming: Option<Receiver<String>>,
+    query: String,
+    /// Indices into `items` of the ones matching `query`, best first.
+    shown: Vec<usize>,
+    selected: usize,
+}
+
+impl Picker {
+    pub fn new(items: Vec<String>) -> Self {
+        let mut picker = Self {
+            items,
+            incoming: None,
+            query: String::new(),
+            shown: Vec::new(),
+            selected: 0,
+        };
+        picker.refilter();
+        picker
+    }
+
+    /// A picker whose items are sent through `incoming`, e.g. from a file scan.
+    pub fn streaming(incoming: Receiver<String>) -> Self {
+        Self {
+            incoming: Some(incoming),
+            ..Self::new(Vec::new())
+        }
+    }
+
+    /** Takes the items that have arrived since the last call. Returns `true` if
+    anything changed, i.e. there were new items or the last one has arrived. */
+    pub fn receive(&mut self) -> bool {
+        let Some(incoming) = &self.incoming else {
+            return false;
+        };
+        let start = self.items.len();
+        for _ in 0..RECEIVE_BATCH {
+            match incoming.try_recv() {
+                Ok(item) => self.items.push(item),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.incoming = None;
+                    break;
+                }
+            }
+        }
+        if self.items.len() > start {
+            self.refilter();
+        }
+        self.items.len() > start || self.incoming.is_none()
+    }
+
+    /// Whether more items may still arrive.
+    pub fn is_loading(&self) -> bool {
+        self.incoming.is_some()
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.selected = 0;
+        self.refilter();
+    }
+
+    fn refilter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, item)| fuzzy_score(item, &self.query).map(|score| (score, idx)))
+            .collect();
+        // Stable, so equally good items keep the order they arrived in
+        scored.sort_by_key(|(score, _)| -score);
+        self.shown = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.selected = self.selected.min(self.shown.len().saturating_sub(1));
+    }
+
+    /// The matching items, best first.
+    pub fn shown(&self) -> impl Iterator<Item = &str> {
+        self.shown.iter().map(|idx| self.items[*idx].as_str())
+    }
+
+    /// Every item received so far, in the order they arrived.
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    pub fn shown_count(&self) -> usize {
+        self.shown.len()
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn selected_idx(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.shown
+            .get(self.selected)
+            .map(|idx| self.items[*idx].as_str())
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.shown.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::buffer::{find_matches, SearchOptions, WordChars};
+use crate::glob;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Most files a scan reports, so huge trees can't keep it busy forever.
+pub const MAX_SCANNED_FILES: usize = 50_000;
+
+/// A line of a `.gitignore`, along with the directory it was found in.
+struct IgnoreRule {
+    /// Directory of the `.gitignore`, relative to the root, ending in `/` unless it's the root.
+    base: String,
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &str, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // Patterns with a slash are relative to the `.gitignore`, others match at any depth
+        let pattern = match line.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if line.contains('/') => line.to_string(),
+            None => format!("**/{}", line),
+        };
+        Some(Self {
+            base: base.to_string(),
+            pattern,
+            negated,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        relative
+            .strip_prefix(&self.base)
+            .is_some_and(|rest| glob::matches(&self.pattern, rest))
+    }
+}
+
+/// Whether the last rule matching a path ignores it, as git decides.
+fn is_ignored(rules: &[IgnoreRule], relative: &str, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(relative, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+/** Lists the files under `root` on a background thread, sending each path relative
+to `root` as it's found. Ignored files, per any `.gitignore` files, and the `.git`
+directory are skipped. The scan stops after `MAX_SCANNED_FILES` files, or as soon as
+the receiver is dropped. */
+pub fn spawn_file_scan(root: PathBuf) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut rules = Vec::new();
+        let mut pending = vec![String::new()];
+        let mut sent = 0;
+        while let Some(dir) = pending.pop() {
+            let dir_path = root.join(&dir);
+            if let Ok(contents) = fs::read_to_string(dir_path.join(".gitignore")) {
+                rules.extend(
+                    contents
+                        .lines()
+                        .filter_map(|line| IgnoreRule::parse(&dir, line)),
+                );
+            }
+            let Ok(entries) = fs::read_dir(&dir_path) else {
+                continue;
+            };
+            let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+                let relative = format!("{}{}", dir, name);
+                if name == ".git" || is_ignored(&rules, &relative, is_dir) {
+                    continue;
+                }
+                if is_dir {
+                    pending.push(format!("{}/", relative));
+                    continue;
+                }
+                if sender.send(relative).is_err() || sent + 1 >= MAX_SCANNED_FILES {
+                    return;
+                }
+                sent += 1;
+            }
+        }
+    });
+    receiver
+}
+
+/// Files bigger than this are skipped by `spawn_grep`.
+const MAX_GREP_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/** Searches the files under `root` for `query` on a background thread, matching
+the same way in-file search does. Each match is sent as `path:line:column: text`,
+with the path relative to `root` and the line and column counting from 1.
+Binary files and very large files are skipped. The search stops as soon as the
+receiver is dropped. */
+pub fn spawn_grep(
+    root: PathBuf,
+    query: String,
+    options: SearchOptions,
+    word_chars: WordChars,
+) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for relative in spawn_file_scan(root.clone()) {
+            let path = root.join(&relative);
+            if fs::metadata(&path).map_or(true, |metadata| metadata.len() > MAX_GREP_FILE_SIZE) {
+                continue;
+            }
+            let Ok(contents) = fs::read_to_string(&path) else {
+                continue; // Not UTF-8, so most likely binary
+            };
+            if contents.contains('\0') {
+                continue;
+            }
+            for (line_idx, line) in contents.lines().enumerate() {
+                for range in find_matches(line, &query, options, &word_chars) {
+                    let result = format!(
+                        "{}:{}:{}: {}",
+                        relative,
+                        line_idx + 1,
+                        range.start + 1,
+                        line.trim()
+                    );
+                    if sender.send(result).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/** Scores how well `query` fuzzily matches `candidate`, or `None` if its characters
+don't all appear in order. Matches that are consecutive or start a path component
+or word score higher, matches within the file name higher still, and shorter
+candidates win ties. Matching ignores case unless the query has an uppercase letter. */
+pub fn fuzzy_score(candidate: &str, query: &str) -> Option<i64> {
+    const FILE_NAME_BONUS: i64 = 10;
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let whole = subsequence_score(candidate, query, case_sensitive)?;
+    let file_name = &candidate[candidate.rfind('/').map_or(0, |idx| idx + 1)..];
+    let in_file_name = subsequence_score(file_name, query, case_sensitive)
+        .map_or(0, |score| score + FILE_NAME_BONUS);
+    Some(whole.max(in_file_name) * 100 - candidate.len() as i64)
+}
+
+/// Scores the leftmost match of `query` as a subsequence of `text`.
+fn subsequence_score(text: &str, query: &str, case_sensitive: bool) -> Option<i64> {
+    let mut score = 0;
+    // Byte index just past the previous match
+    let mut previous_end = None;
+    let mut chars = text.char_indices();
+    for wanted in query.chars() {
+        let (idx, ch) = chars.by_ref().find(|(_, ch)| {
+            if case_sensitive {
+                *ch == wanted
+            } else {
+                ch.to_lowercase().eq(wanted.to_lowercase())
+            }
+        })?;
+        score += 1;
+        if previous_end == Some(idx) {
+            score += 5;
+        }
+        let before = text[..idx].chars().next_back();
+        if before.is_none_or(|ch| matches!(ch, '/' | '_' | '-' | '.' | ' ')) {
+            score += 8;
+        }
+        previous_end = Some(idx + ch.len_utf8());
+    }
+    Some(score)
+}
@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+/// A location to visit, e.g. a search match or a compiler error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickfixEntry {
+    pub path: PathBuf,
+    /// Counting from 1, as tools report it.
+    pub line: usize,
+    /// Counting from 1, as tools report it.
+    pub column: usize,
+    pub message: String,
+}
+
+impl QuickfixEntry {
+    /** Parses a `path:line:column: message` line, as grep and most compilers print
+    it, with relative paths taken from `root`. The column is optional. */
+    pub fn parse(line: &str, root: &Path) -> Option<Self> {
+        let (path, rest) = line.split_once(':')?;
+        let (line_number, rest) = rest.split_once(':').unwrap_or((rest, ""));
+        let line_number = line_number.trim().parse().ok()?;
+        // Without a column, everything after the line number is the message
+        let (column, message) = rest
+            .split_once(':')
+            .and_then(|(column, message)| Some((column.trim().parse().ok()?, message)))
+            .unwrap_or((1, rest));
+        let path = path.trim();
+        if path.is_empty() {
+            return None;
+        }
+        Some(Self {
+            path: root.join(path),
+            line: line_number,
+            column,
+            message: message.trim().to_string(),
+        })
+    }
+
+    /// Formats the entry the way `parse` reads it, with the path relative to `root` if it's inside it.
+    pub fn describe(&self, root: &Path) -> String {
+        let path = self.path.strip_prefix(root).unwrap_or(&self.path);
+        format!(
+            "{}:{}:{}: {}",
+            path.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+/** The list of locations being worked through, and which one was visited last. */
+#[derive(Debug, Default)]
+pub struct QuickfixList {
+    entries: Vec<QuickfixEntry>,
+    current: Option<usize>,
+}
+
+impl QuickfixList {
+    pub fn new(entries: Vec<QuickfixEntry>) -> Self {
+        Self {
+            entries,
+            current: None,
+        }
+    }
+
+    /// Collects the locations in a tool's output, skipping lines that aren't locations.
+    pub fn parse(output: &str, root: &Path) -> Self {
+        Self::new(
+            output
+                .lines()
+                .filter_map(|line| QuickfixEntry::parse(line, root))
+                .collect(),
+        )
+    }
+
+    pub fn entries(&self) -> &[QuickfixEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Index of the entry visited last, if any has been.
+    pub fn current_idx(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Moves to the entry at `idx`, if there is one.
+    pub fn select(&mut self, idx: usize) -> Option<&QuickfixEntry> {
+        let entry = self.entries.get(idx)?;
+        self.current = Some(idx);
+        Some(entry)
+    }
+
+    /// Moves to the entry after the current one, or the first if none has been visited.
+    pub fn select_next(&mut self) -> Option<&QuickfixEntry> {
+        let idx = self.current.map_or(0, |idx| idx + 1);
+        self.select(idx)
+    }
+
+    /// Moves to the entry before the current one, or the last if none has been visited.
+    pub fn select_previous(&mut self) -> Option<&QuickfixEntry> {
+        let idx = match self.current {
+            Some(idx) => idx.checked_sub(1)?,
+            None => self.entries.len().checked_sub(1)?,
+        };
+        self.select(idx)
+    }
+}
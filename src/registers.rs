@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+/// The register used when none is named.
+pub const DEFAULT_REGISTER: char = '"';
+
+/** Named registers holding yanked or cut text, vim-style. Registers are named by
+a letter or digit, and naming an uppercase letter appends to the lowercase one.
+Text ending in a line ending was yanked linewise. */
+#[derive(Debug, Default)]
+pub struct Registers {
+    contents: HashMap<char, String>,
+}
+
+impl Registers {
+    /// Reads a register name given as `a` or vim-style as `"a`.
+    pub fn parse_name(name: &str) -> Option<char> {
+        let name = match name.strip_prefix(DEFAULT_REGISTER) {
+            Some(rest) if !rest.is_empty() => rest,
+            _ => name,
+        };
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(name), None) if name == DEFAULT_REGISTER || name.is_ascii_alphanumeric() => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    /** Stores `text` in the register `name`, or the default one when `name` is `None`.
+    The default register always gets a copy, so pasting without a name pastes the
+    last thing yanked. */
+    pub fn set(&mut self, name: Option<char>, text: String) {
+        let text = match name {
+            Some(name) if name.is_ascii_uppercase() => {
+                let register = self.contents.entry(name.to_ascii_lowercase()).or_default();
+                register.push_str(&text);
+                register.clone()
+            }
+            Some(name) if name != DEFAULT_REGISTER => {
+                self.contents.insert(name, text.clone());
+                text
+            }
+            _ => text,
+        };
+        self.contents.insert(DEFAULT_REGISTER, text);
+    }
+
+    pub fn get(&self, name: Option<char>) -> Option<&str> {
+        let name = name.map_or(DEFAULT_REGISTER, |name| name.to_ascii_lowercase());
+        self.contents.get(&name).map(String::as_str)
+    }
+
+    /// The registers that hold something, the default one first and the rest by name.
+    pub fn filled(&self) -> Vec<(char, &str)> {
+        let mut filled: Vec<(char, &str)> = self
+            .contents
+            .iter()
+            .map(|(name, text)| (*name, text.as_str()))
+            .collect();
+        filled.sort_by_key(|(name, _)| (*name != DEFAULT_REGISTER, *name));
+        filled
+    }
+}
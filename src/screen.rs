@@ -1,36 +1,195 @@
-use crate::buffer::Buffer;
+use crate::buffer::{
+    caret_notation, char_width, find_matches, is_line_break, Buffer, SearchOptions, TabStops,
+};
+use crate::config::{Config, PathDisplay};
+use crate::event_handler::EventHandler;
+use crate::path::{display_path, elide_path};
+use crate::picker::Picker;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use crossterm::terminal::ClearType;
-use crossterm::{cursor, execute, queue, style, terminal};
+use crossterm::{cursor, execute, queue, style, terminal, Command};
 use ropey::RopeSlice;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt;
 use std::io::{stdout, Stdout, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::time::{self, Duration};
 use unicode_width::UnicodeWidthChar;
 
-const TAB_WIDTH: usize = 8;
+/// Smallest terminal the editor lays itself out in: a line of text, the message row and the status bar.
+const MIN_HEIGHT: u16 = 3;
+const MIN_WIDTH: u16 = 10;
+
+/// Height of the list shown by `pick`.
+const PICKER_ROWS: usize = 10;
+/// How often `pick` checks for newly arrived items while waiting for keys.
+const PICKER_POLL_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Most status messages kept waiting to be shown.
+const MAX_QUEUED_MESSAGES: usize = 5;
 
 pub struct WindowSize {
     pub width: u16,
     pub height: u16,
 }
 
+/// What a prompt asks for. Each kind keeps its own input history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromptKind {
+    Command,
+    Search,
+    Replacement,
+    Path,
+    CodePoint,
+}
+
+/// Previously entered prompt input, most recent first, kept separately for each kind of prompt.
+#[derive(Default)]
+struct PromptHistory {
+    entries: HashMap<PromptKind, VecDeque<String>>,
+}
+
+impl PromptHistory {
+    const MAX_ENTRIES: usize = 50;
+
+    fn add(&mut self, kind: PromptKind, input: &str) {
+        if input.is_empty() {
+            return;
+        }
+        let entries = self.entries.entry(kind).or_default();
+        entries.retain(|entry| entry != input);
+        entries.push_front(input.to_string());
+        entries.truncate(Self::MAX_ENTRIES);
+    }
+
+    fn get(&self, kind: PromptKind, idx: usize) -> Option<&str> {
+        self.entries.get(&kind)?.get(idx).map(String::as_str)
+    }
+}
+
+/// Hooks that let callers of `Screen::prompt_with` extend the input prompt.
+pub trait PromptHandler {
+    /// Text shown between the prompt and the input, e.g. the state of search toggles.
+    fn indicator(&self) -> String {
+        String::new()
+    }
+
+    /// Sees every key before the prompt does and may edit the input. Returns `true` if it used the key.
+    fn on_key(&mut self, _key_event: &KeyEvent, _input: &mut String) -> bool {
+        false
+    }
+}
+
+impl PromptHandler for () {}
+
+/// Alt+C toggles case-insensitive matching and Alt+W whole-word matching.
+impl PromptHandler for SearchOptions {
+    fn indicator(&self) -> String {
+        let mut flags = Vec::new();
+        if self.case_insensitive {
+            flags.push("ignore case");
+        }
+        if self.whole_word {
+            flags.push("whole word");
+        }
+        if flags.is_empty() {
+            String::new()
+        } else {
+            format!("[{}] ", flags.join(", "))
+        }
+    }
+
+    fn on_key(&mut self, key_event: &KeyEvent, _input: &mut String) -> bool {
+        if key_event.kind != KeyEventKind::Press || key_event.modifiers != KeyModifiers::ALT {
+            return false;
+        }
+        match key_event.code {
+            KeyCode::Char('c') => self.case_insensitive = !self.case_insensitive,
+            KeyCode::Char('w') => self.whole_word = !self.whole_word,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The query whose matches are highlighted while searching.
+struct SearchHighlight {
+    query: String,
+    options: SearchOptions,
+    /// Start of the current match, drawn with a stronger highlight.
+    current: Option<usize>,
+}
+
+/// How important a status message is, which decides its color and how long it stays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Info,
+    Warning,
+    /// Errors stay until the next keypress instead of timing out.
+    Error,
+}
+
+/// A message shown above the status bar.
+struct StatusMessage {
+    text: String,
+    severity: Severity,
+    shown_at: time::Instant,
+}
+
 /// The Screen struct represents the terminal screen.
 pub struct Screen {
     win_size: WindowSize,
     stdout: Stdout,
     scroll_offset: usize,
-    status_message: Option<String>,
-    status_message_time: time::Instant,
+    /// First visual column shown, when lines aren't wrapped and the cursor has gone past the right edge.
+    col_offset: usize,
+    /// The front message is the one on screen, the rest wait their turn.
+    status_messages: VecDeque<StatusMessage>,
+    // The last path shown in the status bar, with the mode it was shown in
+    shown_path: Option<(PathBuf, PathDisplay, String)>,
+    progress_drawn: Option<time::Instant>,
+    spinner_frame: usize,
+    search_highlight: Option<SearchHighlight>,
+    prompt_history: PromptHistory,
+    cursor_style: cursor::SetCursorStyle,
+    cursor_blink: bool,
+    /// Distraction-free mode: the status bar is hidden and the text gets its row.
+    zen: bool,
+    /// Whether colors are sent at all. Off for `NO_COLOR` and dumb terminals.
+    color: bool,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Screen {
     pub fn new() -> Self {
         let (width, height) = terminal::size().expect("Failed to get terminal size");
+        Self::with_size(width, height)
+    }
+
+    /// A screen laid out for a terminal of the given size, without asking the terminal.
+    fn with_size(width: u16, height: u16) -> Self {
         Self {
             win_size: WindowSize { width, height },
             stdout: stdout(),
             scroll_offset: 0,
-            status_message: None,
-            status_message_time: time::Instant::now(),
+            col_offset: 0,
+            status_messages: VecDeque::new(),
+            shown_path: None,
+            progress_drawn: None,
+            spinner_frame: 0,
+            search_highlight: None,
+            prompt_history: PromptHistory::default(),
+            cursor_style: cursor::SetCursorStyle::DefaultUserShape,
+            cursor_blink: true,
+            zen: false,
+            color: color_supported(),
         }
     }
 
@@ -38,18 +197,35 @@ impl Screen {
         &self.win_size
     }
 
-    pub fn update_window_size(&mut self, width: u16, height: u16) -> crossterm::Result<()> {
+    /// Takes on the new terminal size, scrolling so the cursor is still in view.
+    pub fn update_window_size(
+        &mut self,
+        width: u16,
+        height: u16,
+        buffer: &Buffer,
+        config: &Config,
+    ) -> crossterm::Result<()> {
+        self.win_size = WindowSize { width, height };
+        self.update_scroll_offset(buffer, config);
+        self.refresh()
+    }
+
+    /// Takes on the new terminal size while a prompt or picker has the screen.
+    fn resize(&mut self, width: u16, height: u16) -> crossterm::Result<()> {
         self.win_size = WindowSize { width, height };
         self.refresh()
     }
 
     fn draw_eof_indicators(&mut self, start_row: usize) -> crossterm::Result<()> {
-        for row in start_row..self.win_size.height.saturating_sub(1) as usize {
+        for row in start_row..self.text_rows() {
             queue!(
                 self.stdout,
                 cursor::MoveTo(0, row as u16),
                 terminal::Clear(ClearType::CurrentLine),
-                style::SetForegroundColor(style::Color::DarkGrey),
+                if_color(
+                    self.color,
+                    style::SetForegroundColor(style::Color::DarkGrey)
+                ),
                 style::Print("~"),
                 style::ResetColor
             )?;
@@ -57,81 +233,811 @@ impl Screen {
         Ok(())
     }
 
+    /// Shows the version and a few key hints in the middle of an empty screen.
+    fn draw_welcome(&mut self, config: &Config) -> crossterm::Result<()> {
+        let version = format!("stte {}", env!("CARGO_PKG_VERSION"));
+        let lines = [
+            version.as_str(),
+            "",
+            "Ctrl+S  save",
+            "Ctrl+Q  quit",
+            "Alt+X   run a command",
+        ];
+        let viewport_height = self.text_rows();
+        let width = self.text_width(config);
+        let first_row = viewport_height.saturating_sub(lines.len()) / 2;
+        // Row 0 holds the (empty) first line, so the cursor row stays clear
+        for row in 1..viewport_height {
+            queue!(
+                self.stdout,
+                cursor::MoveTo(0, row as u16),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+            let Some(line) = row.checked_sub(first_row).and_then(|idx| lines.get(idx)) else {
+                continue;
+            };
+            let len = line.chars().count();
+            if len <= width {
+                queue!(
+                    self.stdout,
+                    cursor::MoveToColumn(((width - len) / 2) as u16),
+                    if_color(
+                        self.color,
+                        style::SetForegroundColor(style::Color::DarkGrey)
+                    ),
+                    style::Print(line),
+                    style::ResetColor
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn clear(&mut self) -> crossterm::Result<()> {
         queue!(self.stdout, terminal::Clear(ClearType::All))
     }
 
+    pub fn apply_cursor_style(&mut self, config: &Config) -> crossterm::Result<()> {
+        self.cursor_style = config.cursor_style();
+        self.cursor_blink = config.cursor_blink;
+        self.restore_cursor_style()
+    }
+
+    /** Some terminals reset the cursor when they're resized or cleared,
+    so the chosen style is sent again whenever the screen is refreshed. */
+    fn restore_cursor_style(&mut self) -> crossterm::Result<()> {
+        queue!(self.stdout, self.cursor_style)?;
+        if self.cursor_blink {
+            execute!(self.stdout, cursor::EnableBlinking)
+        } else {
+            execute!(self.stdout, cursor::DisableBlinking)
+        }
+    }
+
     pub fn refresh(&mut self) -> crossterm::Result<()> {
         execute!(
             self.stdout,
             terminal::Clear(ClearType::All),
             cursor::MoveTo(0, 0)
-        )
+        )?;
+        self.restore_cursor_style()
+    }
+
+    /** Layout, from the bottom: the status bar on the last row, the row for status
+    messages and prompts above it, then the rows of text. Zen mode hides the status
+    bar, so messages take the last row. Anything smaller than `MIN_HEIGHT` rows
+    can't fit a line of text above both. */
+    fn text_rows(&self) -> usize {
+        self.message_row() as usize
+    }
+
+    /// Index of the first line shown.
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
     }
 
-    pub fn display_buffer(&mut self, buffer: &Buffer) -> crossterm::Result<()> {
-        self.update_scroll_offset(buffer);
-        self.draw_lines(buffer)?;
-        self.draw_status_bar(buffer)?;
-        self.position_cursor(buffer)?;
+    /// Scrolls so `line` is at the top, as far as the cursor allows on the next redraw.
+    pub fn set_scroll_offset(&mut self, line: usize) {
+        self.scroll_offset = line;
+    }
+
+    /// How many lines Page Up and Page Down move by: one screenful.
+    pub fn page_rows(&self) -> usize {
+        self.text_rows().max(1)
+    }
+
+    /// The row of the text area the cursor is on, counting from 0 at the top.
+    pub fn cursor_screen_row(&self, buffer: &Buffer) -> usize {
+        buffer.visible_lines_between(self.scroll_offset, buffer.cursor_row())
+    }
+
+    /** Scrolls so the cursor's line is `row` lines from the top, or as close as the
+    start of the buffer allows. */
+    pub fn scroll_cursor_to_row(&mut self, buffer: &Buffer, row: usize) {
+        let mut top = buffer.cursor_row();
+        for _ in 0..row {
+            match buffer.prev_visible_line(top) {
+                Some(line) => top = line,
+                None => break,
+            }
+        }
+        self.scroll_offset = top;
+    }
+
+    /// Turns distraction-free mode on or off, returning whether it's now on.
+    pub fn toggle_zen(&mut self) -> bool {
+        self.zen = !self.zen;
+        self.zen
+    }
+
+    fn status_row(&self) -> u16 {
+        self.win_size.height.saturating_sub(1)
+    }
+
+    fn message_row(&self) -> u16 {
+        if self.zen {
+            self.win_size.height.saturating_sub(1)
+        } else {
+            self.win_size.height.saturating_sub(2)
+        }
+    }
+
+    fn is_too_small(&self) -> bool {
+        self.win_size.height < MIN_HEIGHT || self.win_size.width < MIN_WIDTH
+    }
+
+    /// Shown instead of the buffer when the layout can't fit.
+    fn draw_too_small(&mut self) -> crossterm::Result<()> {
+        let message: String = "Terminal too small"
+            .chars()
+            .take(self.win_size.width as usize)
+            .collect();
+        queue!(
+            self.stdout,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, 0),
+            style::Print(message)
+        )?;
+        self.stdout.flush()
+    }
+
+    pub fn display_buffer(&mut self, buffer: &Buffer, config: &Config) -> crossterm::Result<()> {
+        if self.is_too_small() {
+            return self.draw_too_small();
+        }
+        self.update_scroll_offset(buffer, config);
+        self.draw_lines(buffer, config)?;
+        if !self.zen {
+            self.draw_status_bar(buffer, config)?;
+        }
+        // Messages still show in zen mode, since errors shouldn't go unnoticed
+        self.draw_status_message(config)?;
+        self.position_cursor(buffer, config)?;
         self.stdout.flush()?;
         Ok(())
     }
 
-    fn update_scroll_offset(&mut self, buffer: &Buffer) {
+    fn update_scroll_offset(&mut self, buffer: &Buffer, config: &Config) {
         let cursor_row = buffer.cursor_row();
-        let viewport_height = self.win_size.height.saturating_sub(1) as usize;
+        let viewport_height = self.text_rows();
 
+        // Folding can hide the line the view started at, so start at its header instead.
+        // The view can't start past the last line either, e.g. after lines were deleted.
+        let last_line = buffer.shown_line_count() - 1;
+        self.scroll_offset = buffer
+            .prev_visible_line(self.scroll_offset.min(last_line) + 1)
+            .unwrap_or(0);
         if cursor_row < self.scroll_offset {
             self.scroll_offset = cursor_row;
-        } else if cursor_row >= self.scroll_offset + viewport_height {
-            self.scroll_offset = cursor_row.saturating_sub(viewport_height).saturating_add(1);
+        } else if config.wrap {
+            // Take in as many lines above the cursor's row as fit, but no more than before
+            let mut rows = self.cursor_wrap_position(buffer, config).0 + 1;
+            let mut top = cursor_row;
+            while top > self.scroll_offset {
+                let Some(line) = buffer.prev_visible_line(top) else {
+                    break;
+                };
+                rows += self.wrap_starts(buffer, line, config).len();
+                if rows > viewport_height {
+                    break;
+                }
+                top = line;
+            }
+            self.scroll_offset = top;
+        } else if buffer.visible_lines_between(self.scroll_offset, cursor_row) >= viewport_height {
+            let mut top = cursor_row;
+            for _ in 1..viewport_height {
+                match buffer.prev_visible_line(top) {
+                    Some(line) => top = line,
+                    None => break,
+                }
+            }
+            self.scroll_offset = top;
+        }
+        self.update_col_offset(buffer, config);
+    }
+
+    /** Scrolls sideways so the cursor's column is in view, keeping it off the
+    columns the clip markers are drawn in. Wrapped lines always start at column 0. */
+    fn update_col_offset(&mut self, buffer: &Buffer, config: &Config) {
+        if config.wrap {
+            self.col_offset = 0;
+            return;
+        }
+        let width = self.text_width(config);
+        let margin = usize::from(config.clip_markers);
+        let cursor_x = buffer.get_visual_cursor_x();
+        if cursor_x < self.col_offset + margin.min(self.col_offset) {
+            self.col_offset = cursor_x.saturating_sub(margin);
+        } else if cursor_x + margin >= self.col_offset + width {
+            self.col_offset = (cursor_x + margin + 1).saturating_sub(width).min(cursor_x);
+        }
+    }
+
+    /// Columns the wrap marker takes at the start of the rows a wrapped line continues on.
+    fn wrap_indent(&self, config: &Config) -> usize {
+        usize::from(config.wrap_markers)
+    }
+
+    /** Visual columns where each screen row of a line starts: just 0 unless lines
+    are wrapped. Rows break between characters, and the rows after the first lose
+    the columns the wrap marker takes. A line that exactly fills its last row gets
+    an empty row after it, for the cursor to sit on at its end. */
+    fn wrap_starts(&self, buffer: &Buffer, line_idx: usize, config: &Config) -> Vec<usize> {
+        let mut starts = vec![0];
+        if !config.wrap {
+            return starts;
+        }
+        let width = self.text_width(config).max(1);
+        let continued_width = width.saturating_sub(self.wrap_indent(config)).max(1);
+        let tab_stops = buffer.tab_stops();
+        let mut row_start = 0;
+        let mut room = width;
+        let mut column = 0;
+        for ch in buffer.get_line(line_idx).chars() {
+            if is_line_break(ch) {
+                break;
+            }
+            let ch_width = char_width(ch, column, &tab_stops);
+            if column + ch_width > row_start + room && column > row_start {
+                starts.push(column);
+                row_start = column;
+                room = continued_width;
+            }
+            column += ch_width;
+        }
+        if column > row_start && column == row_start + room {
+            starts.push(column);
+        }
+        starts
+    }
+
+    /** Which of its line's screen rows the cursor is on, counting from 0, and its
+    column on that row. */
+    fn cursor_wrap_position(&self, buffer: &Buffer, config: &Config) -> (usize, usize) {
+        let starts = self.wrap_starts(buffer, buffer.cursor_row(), config);
+        let cursor_x = buffer.get_visual_cursor_x();
+        let row = starts
+            .iter()
+            .rposition(|start| *start <= cursor_x)
+            .unwrap_or(0);
+        let indent = if row > 0 { self.wrap_indent(config) } else { 0 };
+        (row, cursor_x - starts[row] + indent)
+    }
+
+    /// Screen rows the lines from the top of the view down to `line_idx` take, not counting `line_idx`.
+    fn rows_above(&self, buffer: &Buffer, line_idx: usize, config: &Config) -> usize {
+        if !config.wrap {
+            return buffer.visible_lines_between(self.scroll_offset, line_idx);
+        }
+        let mut rows = 0;
+        let mut line = self.scroll_offset;
+        while line < line_idx {
+            rows += self.wrap_starts(buffer, line, config).len();
+            match buffer.next_visible_line(line) {
+                Some(next) => line = next,
+                None => break,
+            }
+        }
+        rows
+    }
+
+    fn draw_lines(&mut self, buffer: &Buffer, config: &Config) -> crossterm::Result<()> {
+        let viewport_height = self.text_rows();
+        let mut visible_lines = Vec::with_capacity(viewport_height);
+        let mut next_line = Some(self.scroll_offset).filter(|idx| *idx < buffer.shown_line_count());
+        while let Some(line_idx) = next_line {
+            if visible_lines.len() >= viewport_height {
+                break;
+            }
+            visible_lines.push(line_idx);
+            next_line = buffer.next_visible_line(line_idx);
+        }
+
+        let cursor_word = if config.highlight_cursor_word {
+            buffer.word_at_cursor()
+        } else {
+            None
+        };
+
+        let tab_stops = buffer.tab_stops();
+        let text_width = self.text_width(config);
+        let mut row = 0;
+        for &line_idx in &visible_lines {
+            if row >= viewport_height {
+                break;
+            }
+            let line = buffer.get_line(line_idx);
+            let line_text = line.to_string();
+            let mut highlights = Vec::new();
+            // Earlier highlights win where they overlap, so search matches come first
+            if let Some(search) = &self.search_highlight {
+                let line_start = buffer.line_to_char(line_idx);
+                highlights.extend(
+                    find_matches(
+                        &line_text,
+                        &search.query,
+                        search.options,
+                        buffer.word_chars(),
+                    )
+                    .into_iter()
+                    .map(|range| {
+                        let color = if search.current == Some(line_start + range.start) {
+                            config.theme.current_match
+                        } else {
+                            config.theme.search_match
+                        };
+                        (range, color)
+                    }),
+                );
+            }
+            if let Some(word) = &cursor_word {
+                highlights.extend(
+                    find_matches(
+                        &line_text,
+                        word,
+                        SearchOptions {
+                            whole_word: true,
+                            ..SearchOptions::default()
+                        },
+                        buffer.word_chars(),
+                    )
+                    .into_iter()
+                    .map(|range| (range, config.theme.word_highlight)),
+                );
+            }
+            if config.highlight_trailing_whitespace && line_idx != buffer.cursor_row() {
+                let content_end = buffer.line_content_end(line_idx) - buffer.line_to_char(line_idx);
+                let trailing = line
+                    .chars_at(content_end)
+                    .reversed()
+                    .take_while(|ch| matches!(ch, ' ' | '\t'))
+                    .count();
+                if trailing > 0 {
+                    highlights.push((
+                        content_end - trailing..content_end,
+                        config.theme.trailing_whitespace,
+                    ));
+                }
+            }
+            let line_start = buffer.line_to_char(line_idx);
+            let selected = buffer
+                .selection_range()
+                .map(|(start, end)| {
+                    start.saturating_sub(line_start)..end.saturating_sub(line_start)
+                })
+                .filter(|range| !range.is_empty());
+            let mut drawn = 0;
+            for (wrap_row, start) in self
+                .wrap_starts(buffer, line_idx, config)
+                .into_iter()
+                .enumerate()
+            {
+                if row >= viewport_height {
+                    break;
+                }
+                queue!(self.stdout, cursor::MoveTo(0, row as u16))?;
+                let mut indent = 0;
+                if wrap_row > 0 && config.wrap_markers {
+                    queue!(
+                        self.stdout,
+                        if_color(
+                            self.color,
+                            style::SetForegroundColor(config.theme.wrap_marker)
+                        ),
+                        style::Print('↳'),
+                        if_color(self.color, style::SetForegroundColor(style::Color::Reset))
+                    )?;
+                    indent = self.wrap_indent(config);
+                }
+                let start = if config.wrap { start } else { self.col_offset };
+                drawn = indent
+                    + self.draw_line(
+                        &line,
+                        start..start + text_width.saturating_sub(indent),
+                        &highlights,
+                        selected.clone(),
+                        &tab_stops,
+                        config,
+                    )?;
+                row += 1;
+            }
+            if let Some(fold) = buffer.fold_at(line_idx) {
+                self.draw_fold_marker(fold.len() - 1, drawn, config)?;
+            }
+        }
+
+        if buffer.is_untouched() {
+            self.draw_welcome(config)?;
+        } else {
+            self.draw_eof_indicators(row)?;
+        }
+        if config.minimap {
+            self.draw_minimap(buffer, config)?;
+        }
+        if config.scrollbar {
+            self.draw_scrollbar(buffer.line_count(), config)?;
+        }
+        Ok(())
+    }
+
+    /// Width left for text once columns reserved for decorations like the scrollbar are taken.
+    fn text_width(&self, config: &Config) -> usize {
+        (self.win_size.width as usize)
+            .saturating_sub(self.scrollbar_width(config))
+            .saturating_sub(self.minimap_width(config))
+    }
+
+    fn scrollbar_width(&self, config: &Config) -> usize {
+        if config.scrollbar {
+            1
+        } else {
+            0
         }
     }
 
-    fn draw_lines(&mut self, buffer: &Buffer) -> crossterm::Result<()> {
-        let viewport_height = self.win_size.height.saturating_sub(1) as usize;
-        let visible_lines = buffer
-            .lines()
-            .skip(self.scroll_offset)
-            .take(viewport_height);
+    fn minimap_width(&self, config: &Config) -> usize {
+        if config.minimap {
+            // Always leave at least half of the screen for text
+            config.minimap_width.min(self.win_size.width as usize / 2)
+        } else {
+            0
+        }
+    }
 
-        for (row, line) in visible_lines.enumerate() {
-            queue!(self.stdout, cursor::MoveTo(0, row as u16))?;
-            self.draw_line(&line)?;
+    /** Draws a condensed overview of the whole buffer to the left of the scrollbar.
+    Each row stands for a group of lines and each cell for a few columns of text,
+    shaded by how many lines in the group reach that far. The rows covering the
+    visible part of the buffer are highlighted. */
+    fn draw_minimap(&mut self, buffer: &Buffer, config: &Config) -> crossterm::Result<()> {
+        const COLUMNS_PER_CELL: usize = 4;
+        let viewport_height = self.text_rows();
+        let width = self.minimap_width(config);
+        if width == 0 || viewport_height == 0 {
+            return Ok(());
+        }
+        let start_column = self.text_width(config) as u16;
+        let total_lines = buffer.line_count();
+        let lines_per_row = total_lines.div_ceil(viewport_height).max(1);
+        let visible = self.scroll_offset..self.scroll_offset + viewport_height;
+
+        for row in 0..viewport_height {
+            let first_line = row * lines_per_row;
+            let group = first_line..(first_line + lines_per_row).min(total_lines);
+            let lengths: Vec<usize> = group
+                .clone()
+                .map(|idx| {
+                    buffer
+                        .get_line(idx)
+                        .chars()
+                        .take_while(|ch| *ch != '\r' && *ch != '\n')
+                        .count()
+                })
+                .collect();
+            let in_view =
+                !group.is_empty() && group.start < visible.end && visible.start < group.end;
+            let background = if in_view {
+                config.theme.minimap_viewport
+            } else {
+                style::Color::Reset
+            };
+            let cells: String = (0..width)
+                .map(|cell| {
+                    let reaching = lengths
+                        .iter()
+                        .filter(|len| **len > cell * COLUMNS_PER_CELL)
+                        .count();
+                    if reaching == 0 {
+                        ' '
+                    } else if reaching * 3 < lines_per_row {
+                        '░'
+                    } else if reaching * 3 < lines_per_row * 2 {
+                        '▒'
+                    } else {
+                        '▓'
+                    }
+                })
+                .collect();
+            queue!(
+                self.stdout,
+                cursor::MoveTo(start_column, row as u16),
+                if_color(self.color, style::SetBackgroundColor(background)),
+                if_color(
+                    self.color,
+                    style::SetForegroundColor(style::Color::DarkGrey)
+                ),
+                reverse_without_color(self.color, in_view),
+                style::Print(cells),
+                style::ResetColor,
+                reverse_without_color(self.color, false)
+            )?;
         }
+        Ok(())
+    }
+
+    /** Draws a one column scrollbar on the right edge, where the thumb shows
+    which part of the buffer is visible. */
+    fn draw_scrollbar(&mut self, total_lines: usize, config: &Config) -> crossterm::Result<()> {
+        let viewport_height = self.text_rows();
+        let column = self.win_size.width.saturating_sub(1);
+        let total_lines = total_lines.max(1);
+        let thumb_height =
+            (viewport_height * viewport_height / total_lines).clamp(1, viewport_height.max(1));
+        let thumb_start = (self.scroll_offset * viewport_height / total_lines)
+            .min(viewport_height.saturating_sub(thumb_height));
 
-        self.draw_eof_indicators(buffer.lines().count().saturating_sub(self.scroll_offset))?;
+        for row in 0..viewport_height {
+            let in_thumb = (thumb_start..thumb_start + thumb_height).contains(&row);
+            let color = if in_thumb {
+                config.theme.scrollbar_thumb
+            } else {
+                config.theme.scrollbar_track
+            };
+            queue!(
+                self.stdout,
+                cursor::MoveTo(column, row as u16),
+                if_color(self.color, style::SetBackgroundColor(color)),
+                reverse_without_color(self.color, in_thumb),
+                style::Print(' '),
+                if_color(self.color, style::SetBackgroundColor(style::Color::Reset)),
+                reverse_without_color(self.color, false)
+            )?;
+        }
         Ok(())
     }
 
-    fn draw_line(&mut self, line: &RopeSlice) -> crossterm::Result<()> {
-        let mut visual_col = 0;
+    /// Marks a folded header line with how many lines it hides, if there's room after its text.
+    fn draw_fold_marker(
+        &mut self,
+        hidden: usize,
+        drawn: usize,
+        config: &Config,
+    ) -> crossterm::Result<()> {
+        let marker = format!(" ··· {} lines", hidden);
+        let marker_width = marker.chars().count();
+        if drawn + marker_width > self.text_width(config) {
+            return Ok(());
+        }
+        queue!(
+            self.stdout,
+            cursor::MoveToColumn(drawn as u16),
+            if_color(
+                self.color,
+                style::SetForegroundColor(style::Color::DarkGrey)
+            ),
+            style::Print(marker),
+            style::ResetColor
+        )
+    }
+
+    /** Draws the part of a line between the visual columns in `view` and returns how
+    many columns it took. A character cut by the left edge of the view is drawn as
+    blanks, and one that doesn't fit before the right edge isn't drawn. `highlights`
+    are char column ranges drawn with the given background color, e.g. occurrences of
+    the word under the cursor. The `selected` columns are drawn in reverse video. */
+    fn draw_line(
+        &mut self,
+        line: &RopeSlice,
+        view: Range<usize>,
+        highlights: &[(Range<usize>, style::Color)],
+        selected: Option<Range<usize>>,
+        tab_stops: &TabStops,
+        config: &Config,
+    ) -> crossterm::Result<usize> {
+        let mut column = 0;
+        let mut drawn = 0;
+        // Widths of the first and last characters drawn, so the clip markers can blank them whole
+        let mut first_width = None;
+        let mut last_width = 0;
+        let mut current_style = (None, false);
+        let mut clipped = false;
 
-        for ch in line.chars() {
-            if visual_col >= self.win_size.width as usize {
+        for (idx, ch) in line.chars().enumerate() {
+            if is_line_break(ch) {
+                break;
+            }
+            let ch_width = char_width(ch, column, tab_stops);
+            if column < view.start && column + ch_width <= view.start {
+                column += ch_width;
+                continue;
+            }
+            if column + ch_width > view.end {
+                clipped = true;
                 break;
             }
 
+            let background = highlights
+                .iter()
+                .find(|(range, _)| range.contains(&idx))
+                .map(|(_, color)| *color);
+            let is_selected = selected.as_ref().is_some_and(|range| range.contains(&idx));
+            if (background, is_selected) != current_style {
+                // Without color, highlights are shown in reverse video too
+                let reverse = is_selected || (!self.color && background.is_some());
+                queue!(
+                    self.stdout,
+                    if_color(
+                        self.color,
+                        style::SetBackgroundColor(background.unwrap_or(style::Color::Reset))
+                    ),
+                    style::SetAttribute(if reverse {
+                        style::Attribute::Reverse
+                    } else {
+                        style::Attribute::NoReverse
+                    })
+                )?;
+                current_style = (background, is_selected);
+            }
+
+            let cut = view.start.saturating_sub(column);
             match ch {
-                '\t' => {
-                    let spaces = TAB_WIDTH - (visual_col % TAB_WIDTH);
-                    queue!(self.stdout, style::Print(" ".repeat(spaces)))?;
-                    visual_col += spaces;
+                _ if cut > 0 => queue!(self.stdout, style::Print(" ".repeat(ch_width - cut)))?,
+                '\t' => self.draw_tab(ch_width, config.tab_fill())?,
+                _ if caret_notation(ch).is_some() => {
+                    let shown = caret_notation(ch).unwrap_or('?');
+                    queue!(
+                        self.stdout,
+                        if_color(
+                            self.color,
+                            style::SetForegroundColor(config.theme.control_char)
+                        ),
+                        style::Print('^'),
+                        style::Print(shown),
+                        if_color(self.color, style::SetForegroundColor(style::Color::Reset))
+                    )?;
                 }
-                '\n' => break,
-                _ => {
-                    queue!(self.stdout, style::Print(ch))?;
-                    visual_col += 1;
+                _ => queue!(self.stdout, style::Print(ch))?,
+            }
+            let shown = ch_width - cut;
+            if shown > 0 {
+                first_width.get_or_insert(shown);
+                last_width = shown;
+            }
+            drawn += shown;
+            column += ch_width;
+        }
+
+        if current_style != (None, false) {
+            queue!(
+                self.stdout,
+                if_color(self.color, style::SetBackgroundColor(style::Color::Reset)),
+                style::SetAttribute(style::Attribute::NoReverse)
+            )?;
+        }
+        queue!(self.stdout, terminal::Clear(ClearType::UntilNewLine))?;
+        // Wrapped lines have their own marker for where they go on
+        if config.clip_markers && !config.wrap {
+            let width = view.len();
+            let has_text = line.len_chars() > 0 && !is_line_break(line.char(0));
+            if view.start > 0 && has_text {
+                self.draw_clip_marker(0, first_width.unwrap_or(1), '‹', config)?;
+            }
+            if clipped && width > 0 {
+                if drawn < width {
+                    self.draw_clip_marker(width - 1, 1, '›', config)?;
+                } else {
+                    self.draw_clip_marker(drawn - last_width, last_width, '›', config)?;
                 }
             }
         }
+        Ok(drawn)
+    }
 
-        queue!(self.stdout, terminal::Clear(ClearType::UntilNewLine))
+    /** Draws a clip marker at `column`, over a character `width` columns wide. The rest of
+    the character is blanked, since half of a wide character can't be drawn over. */
+    fn draw_clip_marker(
+        &mut self,
+        column: usize,
+        width: usize,
+        marker: char,
+        config: &Config,
+    ) -> crossterm::Result<()> {
+        let blank = " ".repeat(width.saturating_sub(1));
+        queue!(self.stdout, cursor::MoveToColumn(column as u16))?;
+        if marker == '›' {
+            queue!(self.stdout, style::Print(&blank))?;
+        }
+        queue!(
+            self.stdout,
+            if_color(
+                self.color,
+                style::SetForegroundColor(config.theme.clip_marker)
+            ),
+            style::Print(marker),
+            if_color(self.color, style::SetForegroundColor(style::Color::Reset))
+        )?;
+        if marker == '‹' {
+            queue!(self.stdout, style::Print(&blank))?;
+        }
+        Ok(())
     }
 
-    fn draw_status_bar(&mut self, buffer: &Buffer) -> crossterm::Result<()> {
-        let status_row = self.win_size.height.saturating_sub(1);
+    /// Draws an expanded tab: the fill character in the first cell, then spaces up to the tab stop.
+    fn draw_tab(&mut self, width: usize, fill: char) -> crossterm::Result<()> {
+        if fill != ' ' {
+            queue!(
+                self.stdout,
+                if_color(
+                    self.color,
+                    style::SetForegroundColor(style::Color::DarkGrey)
+                ),
+                style::Print(fill),
+                if_color(self.color, style::SetForegroundColor(style::Color::Reset))
+            )?;
+        } else {
+            queue!(self.stdout, style::Print(fill))?;
+        }
+        queue!(self.stdout, style::Print(" ".repeat(width - 1)))
+    }
+
+    /** The buffer's path as shown in the status bar. Working it out touches the
+    file system, so it's only redone when the path or display mode changes. */
+    fn shown_path(&mut self, path: &Path, mode: PathDisplay, root: &Path) -> &str {
+        let stale = self
+            .shown_path
+            .as_ref()
+            .is_none_or(|(cached_path, cached_mode, _)| {
+                cached_path != path || *cached_mode != mode
+            });
+        if stale {
+            self.shown_path = Some((path.to_path_buf(), mode, display_path(path, mode, root)));
+        }
+        self.shown_path.as_ref().map_or("", |(_, _, shown)| shown)
+    }
+
+    /** Fills in the placeholders of the configured status format:
+    `{file}`, `{row}`, `{col}`, `{byte}`, `{char}` and `{indent}`. */
+    fn format_status(&mut self, buffer: &Buffer, config: &Config) -> String {
+        let indent_style = buffer
+            .indent_style()
+            .unwrap_or_else(|| config.indent_style());
+        let mut status = config
+            .status_format
+            .replace("{row}", &(buffer.cursor_row() + 1).to_string())
+            .replace("{col}", &(buffer.cursor_column() + 1).to_string())
+            .replace("{byte}", &buffer.cursor_byte().to_string())
+            .replace("{char}", &buffer.describe_char_at_cursor())
+            .replace("{indent}", &indent_style.to_string());
+        if config.show_byte_offset && !config.status_format.contains("{byte}") {
+            status.push_str(&format!(" - byte {}", buffer.cursor_byte()));
+        }
+        if let Some((lines, chars)) = buffer.selection_size() {
+            status.push_str(&format!(
+                " - {} {}, {} {} selected",
+                lines,
+                if lines == 1 { "line" } else { "lines" },
+                chars,
+                if chars == 1 { "char" } else { "chars" }
+            ));
+        }
+        if config.overwrite {
+            status.push_str(" [OVR]");
+        }
+        // The path gets whatever room the rest of the status leaves
+        let room = (self.win_size.width as usize)
+            .saturating_sub(display_width(&status.replace("{file}", "")));
+        let file_name = match buffer.file_path() {
+            Some(path) => elide_path(
+                self.shown_path(path, config.path_display, buffer.project_root()),
+                room,
+            ),
+            None => "[No Name]".to_string(),
+        };
+        status.replace("{file}", &file_name)
+    }
+
+    /// The status bar's text, cut or padded to exactly the width of the screen.
+    fn status_line(&mut self, buffer: &Buffer, config: &Config) -> String {
+        let width = self.win_size.width as usize;
+        // Anything wider than the screen would wrap and scroll the terminal
+        let mut status = truncate_to_width(&self.format_status(buffer, config), width);
+        // Pad so the reverse video covers the whole row
+        status.push_str(&" ".repeat(width.saturating_sub(display_width(&status))));
+        status
+    }
+
+    fn draw_status_bar(&mut self, buffer: &Buffer, config: &Config) -> crossterm::Result<()> {
+        let status_row = self.status_row();
         queue!(
             self.stdout,
             cursor::MoveTo(0, status_row),
@@ -139,47 +1045,713 @@ impl Screen {
             style::SetAttribute(style::Attribute::Reverse)
         )?;
 
-        let file_name = buffer.file_path().map_or("[No Name]", |path| {
-            path.to_str().unwrap_or("[Invalid Path]")
-        });
-        let cursor_info = format!("{}:{}", buffer.cursor_row() + 1, buffer.cursor_column() + 1);
-        let status = format!("{} - {}", file_name, cursor_info);
-
+        let status = self.status_line(buffer, config);
         queue!(
             self.stdout,
             style::Print(status),
             style::SetAttribute(style::Attribute::Reset)
+        )
+    }
+
+    /** Draws the message at the front of the queue above the status bar, or clears
+    its row when there's none, so nothing is left over from the last message or prompt. */
+    fn draw_status_message(&mut self, config: &Config) -> crossterm::Result<()> {
+        let message_row = self.message_row();
+        let width = self.win_size.width as usize;
+        self.expire_status_message(config.status_message_timeout);
+        queue!(
+            self.stdout,
+            cursor::MoveTo(0, message_row),
+            terminal::Clear(ClearType::CurrentLine)
         )?;
+        if let Some(message) = self.status_messages.front() {
+            let color = match message.severity {
+                Severity::Info => config.theme.message_info,
+                Severity::Warning => config.theme.message_warning,
+                Severity::Error => config.theme.message_error,
+            };
+            let text = truncate_to_width(&message.text, width);
+            queue!(
+                self.stdout,
+                if_color(self.color, style::SetForegroundColor(color)),
+                style::Print(text),
+                style::ResetColor
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /** Queues a message to show once the ones before it are gone. Repeating the
+    last queued message doesn't queue it again, and when the queue is full the
+    oldest waiting message that isn't an error is dropped. */
+    pub fn set_status_message(&mut self, message: String, severity: Severity) {
+        if let Some(last) = self.status_messages.back() {
+            if last.text == message && last.severity == severity {
+                if self.status_messages.len() == 1 {
+                    self.status_messages[0].shown_at = time::Instant::now();
+                }
+                return;
+            }
+        }
+        if self.status_messages.len() >= MAX_QUEUED_MESSAGES {
+            let droppable = self
+                .status_messages
+                .iter()
+                .skip(1)
+                .position(|message| message.severity != Severity::Error);
+            if let Some(idx) = droppable {
+                self.status_messages.remove(idx + 1);
+            }
+        }
+        self.status_messages.push_back(StatusMessage {
+            text: message,
+            severity,
+            shown_at: time::Instant::now(),
+        });
+    }
+
+    /// Moves on to the next queued message, which starts its time on screen now.
+    fn next_status_message(&mut self) {
+        self.status_messages.pop_front();
+        if let Some(next) = self.status_messages.front_mut() {
+            next.shown_at = time::Instant::now();
+        }
+    }
+
+    /** Moves on from the current status message once it's been shown for `timeout`,
+    unless it's an error. Returns `true` if the message changed. */
+    pub fn expire_status_message(&mut self, timeout: Duration) -> bool {
+        let expired = self.status_messages.front().is_some_and(|message| {
+            message.severity != Severity::Error && message.shown_at.elapsed() >= timeout
+        });
+        if expired {
+            self.next_status_message();
+        }
+        expired
+    }
+
+    /// Moves on from an error message. Returns `true` if one was showing.
+    pub fn dismiss_error_message(&mut self) -> bool {
+        let is_error = self
+            .status_messages
+            .front()
+            .is_some_and(|message| message.severity == Severity::Error);
+        if is_error {
+            self.next_status_message();
+        }
+        is_error
+    }
+
+    /** Highlights every visible match of `query`, and the match starting
+    at the char index `current` more strongly. Only visible lines are searched,
+    so this stays cheap while the query or scroll position changes. */
+    pub fn set_search_highlight(
+        &mut self,
+        query: &str,
+        options: SearchOptions,
+        current: Option<usize>,
+    ) {
+        self.search_highlight = Some(SearchHighlight {
+            query: query.to_string(),
+            options,
+            current,
+        });
+    }
+
+    pub fn clear_search_highlight(&mut self) {
+        self.search_highlight = None;
+    }
+
+    /** Shows the progress of a long running operation on the message line,
+    as a percentage when `progress` holds `(done, total)` or as a spinner otherwise.
+    Redraws are throttled so reporting progress often stays cheap. */
+    pub fn show_progress(
+        &mut self,
+        label: &str,
+        progress: Option<(u64, u64)>,
+    ) -> crossterm::Result<()> {
+        const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+        if self
+            .progress_drawn
+            .is_some_and(|drawn| drawn.elapsed() < Duration::from_millis(100))
+        {
+            return Ok(());
+        }
+        self.progress_drawn = Some(time::Instant::now());
+        let text = match progress {
+            Some((done, total)) if total > 0 => {
+                format!("{}... {}%", label, done.min(total) * 100 / total)
+            }
+            _ => {
+                self.spinner_frame = (self.spinner_frame + 1) % SPINNER.len();
+                format!("{}... {}", label, SPINNER[self.spinner_frame])
+            }
+        };
+        self.draw_prompt(&text)
+    }
+
+    /// Removes the progress indicator once the operation is done.
+    pub fn clear_progress(&mut self) -> crossterm::Result<()> {
+        if self.progress_drawn.take().is_some() {
+            self.draw_prompt("")?;
+        }
+        Ok(())
+    }
+
+    /** Asks the user a yes/no question on the message line and blocks
+    until it's answered. `y` or Enter answers yes, `n` or Esc answers no,
+    every other key is ignored. */
+    pub fn confirm(&mut self, prompt: &str) -> crossterm::Result<bool> {
+        let event_handler = EventHandler;
+        loop {
+            self.draw_prompt(prompt)?;
+            match event_handler.get_events()? {
+                Event::Key(KeyEvent {
+                    code,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => match code {
+                    KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => return Ok(true),
+                    KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => return Ok(false),
+                    _ => {}
+                },
+                Event::Resize(width, height) => {
+                    self.resize(width, height)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Shows `prompt` on the message line and waits for the next key press.
+    pub fn read_key(&mut self, prompt: &str) -> crossterm::Result<KeyEvent> {
+        let event_handler = EventHandler;
+        loop {
+            self.draw_prompt(prompt)?;
+            match event_handler.get_events()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => return Ok(key),
+                Event::Resize(width, height) => {
+                    self.resize(width, height)?;
+                }
+                _ => {}
+            }
+        }
+    }
 
-        if let Some(message) = &self.status_message {
-            if self.status_message_time.elapsed() < Duration::from_secs(3) {
+    /** Like `read_key`, but gives up and returns `None` if no key is pressed
+    within `timeout`. */
+    pub fn read_key_within(
+        &mut self,
+        prompt: &str,
+        timeout: Duration,
+    ) -> crossterm::Result<Option<KeyEvent>> {
+        let event_handler = EventHandler;
+        let deadline = time::Instant::now() + timeout;
+        loop {
+            self.draw_prompt(prompt)?;
+            let left = deadline.saturating_duration_since(time::Instant::now());
+            match event_handler.poll_event(left)? {
+                Some(Event::Key(key)) if key.kind == KeyEventKind::Press => return Ok(Some(key)),
+                Some(Event::Resize(width, height)) => {
+                    self.resize(width, height)?;
+                }
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /** Reads a line of input on the message line.
+    Returns `None` if the user cancels with Esc. */
+    pub fn prompt(&mut self, kind: PromptKind, prompt: &str) -> crossterm::Result<Option<String>> {
+        self.prompt_with(kind, prompt, "", &mut ())
+    }
+
+    /** Like `prompt`, but starts with `initial` already typed and
+    lets `handler` react to keys and add to what's shown.
+    Up and Down recall earlier input given to prompts of the same kind. */
+    pub fn prompt_with(
+        &mut self,
+        kind: PromptKind,
+        prompt: &str,
+        initial: &str,
+        handler: &mut impl PromptHandler,
+    ) -> crossterm::Result<Option<String>> {
+        let event_handler = EventHandler;
+        let mut input = initial.to_string();
+        // What was typed before browsing the history, restored when moving back past the newest entry
+        let mut draft = input.clone();
+        let mut history_idx: Option<usize> = None;
+        loop {
+            self.draw_prompt(&format!("{}{}{}", prompt, handler.indicator(), input))?;
+            match event_handler.get_events()? {
+                Event::Key(key_event) if handler.on_key(&key_event, &mut input) => {}
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => match code {
+                    KeyCode::Enter => {
+                        self.prompt_history.add(kind, &input);
+                        return Ok(Some(input));
+                    }
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Up => {
+                        let next = history_idx.map_or(0, |idx| idx + 1);
+                        if let Some(entry) = self.prompt_history.get(kind, next) {
+                            if history_idx.is_none() {
+                                draft = input.clone();
+                            }
+                            input = entry.to_string();
+                            history_idx = Some(next);
+                        }
+                    }
+                    KeyCode::Down => match history_idx {
+                        Some(0) => {
+                            input = draft.clone();
+                            history_idx = None;
+                        }
+                        Some(idx) => {
+                            if let Some(entry) = self.prompt_history.get(kind, idx - 1) {
+                                input = entry.to_string();
+                            }
+                            history_idx = Some(idx - 1);
+                        }
+                        None => {}
+                    },
+                    KeyCode::Char(c)
+                        if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                    {
+                        input.push(c);
+                    }
+                    _ => {}
+                },
+                Event::Resize(width, height) => {
+                    self.resize(width, height)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /** Shows `picker` in a list above the message line and lets the user choose from it.
+    Typing filters the list, Up and Down move the selection, Enter picks and Esc cancels.
+    Items still arriving are added while the list is open. */
+    pub fn pick(&mut self, prompt: &str, picker: &mut Picker) -> crossterm::Result<Option<String>> {
+        let event_handler = EventHandler;
+        let mut redraw = true;
+        loop {
+            redraw |= picker.receive();
+            if redraw {
+                self.draw_picker(prompt, picker)?;
+                redraw = false;
+            }
+            let Some(event) = event_handler.poll_event(PICKER_POLL_TIMEOUT)? else {
+                continue;
+            };
+            redraw = true;
+            match event {
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => match code {
+                    KeyCode::Enter => return Ok(picker.selected().map(str::to_string)),
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Up => picker.select_previous(),
+                    KeyCode::Down => picker.select_next(),
+                    KeyCode::Backspace => {
+                        let mut query = picker.query().to_string();
+                        query.pop();
+                        picker.set_query(query);
+                    }
+                    KeyCode::Char(ch)
+                        if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                    {
+                        picker.set_query(format!("{}{}", picker.query(), ch))
+                    }
+                    _ => {}
+                },
+                Event::Resize(width, height) => {
+                    self.resize(width, height)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn draw_picker(&mut self, prompt: &str, picker: &Picker) -> crossterm::Result<()> {
+        let width = self.win_size.width as usize;
+        let rows = PICKER_ROWS.min(self.message_row() as usize);
+        let first_row = self.message_row() as usize - rows;
+        // Scroll the list so the selection stays in view
+        let scroll = (picker.selected_idx() + 1).saturating_sub(rows);
+        let mut shown = picker.shown().skip(scroll);
+        for row in 0..rows {
+            queue!(
+                self.stdout,
+                cursor::MoveTo(0, (first_row + row) as u16),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+            let Some(item) = shown.next() else {
+                continue;
+            };
+            let item = truncate_to_width(item, width);
+            if scroll + row == picker.selected_idx() {
                 queue!(
                     self.stdout,
-                    cursor::MoveTo(0, status_row.saturating_sub(1)),
-                    terminal::Clear(ClearType::CurrentLine),
-                    style::Print(message)
+                    style::SetAttribute(style::Attribute::Reverse),
+                    style::Print(item),
+                    style::SetAttribute(style::Attribute::Reset)
                 )?;
             } else {
-                self.status_message = None;
+                queue!(self.stdout, style::Print(item))?;
             }
         }
+        let loading = if picker.is_loading() { "…" } else { "" };
+        self.draw_prompt(&format!(
+            "{}{}  [{}/{}{}]",
+            prompt,
+            picker.query(),
+            picker.shown_count(),
+            picker.total_count(),
+            loading
+        ))?;
+        // Leave the cursor after the query
+        let cursor_x = display_width(prompt) + display_width(picker.query());
+        let prompt_row = self.message_row();
+        execute!(
+            self.stdout,
+            cursor::MoveTo(cursor_x.min(width) as u16, prompt_row)
+        )
+    }
 
-        Ok(())
+    fn draw_prompt(&mut self, prompt: &str) -> crossterm::Result<()> {
+        let prompt_row = self.message_row();
+        execute!(
+            self.stdout,
+            cursor::MoveTo(0, prompt_row),
+            terminal::Clear(ClearType::CurrentLine),
+            style::Print(prompt)
+        )
     }
 
-    pub fn set_status_message(&mut self, message: String) {
-        self.status_message = Some(message);
-        self.status_message_time = time::Instant::now();
+    /** Places the terminal cursor from the buffer's logical position on every redraw,
+    so toggling wrap, the minimap, the scrollbar or zen mode can't leave it on a stale
+    cell. It's kept off the columns those decorations take up. */
+    fn position_cursor(&mut self, buffer: &Buffer, config: &Config) -> crossterm::Result<()> {
+        let (x, y) = self.cursor_position(buffer, config);
+        execute!(self.stdout, cursor::MoveTo(x as u16, y as u16))
     }
 
-    fn position_cursor(&mut self, buffer: &Buffer) -> crossterm::Result<()> {
-        let (_, cursor_y) = buffer.get_cursor_xy();
-        let visual_cursor_x = buffer.get_visual_cursor_x();
-        let screen_y = cursor_y.saturating_sub(self.scroll_offset) as u16;
+    /// The cursor's column and row on the screen, counting wrapped rows and sideways scrolling.
+    fn cursor_position(&self, buffer: &Buffer, config: &Config) -> (usize, usize) {
+        let (x, wrap_row) = if config.wrap {
+            let (wrap_row, x) = self.cursor_wrap_position(buffer, config);
+            (x, wrap_row)
+        } else {
+            (
+                buffer.get_visual_cursor_x().saturating_sub(self.col_offset),
+                0,
+            )
+        };
+        let x = x.min(self.text_width(config).saturating_sub(1));
+        let y = (self.rows_above(buffer, buffer.cursor_row(), config) + wrap_row)
+            .min(self.text_rows().saturating_sub(1));
+        (x, y)
+    }
+}
 
-        execute!(
-            self.stdout,
-            cursor::MoveTo(visual_cursor_x as u16, screen_y)
+/// Number of terminal columns `text` takes up.
+fn display_width(text: &str) -> usize {
+    text.chars().map(|ch| ch.width().unwrap_or(1)).sum()
+}
+
+/// Cuts `text` down to `width` columns, ending it with `…` if anything was cut.
+fn truncate_to_width(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for ch in text.chars() {
+        let ch_width = ch.width().unwrap_or(1);
+        // Leave a column for the ellipsis
+        if used + ch_width + 1 > width {
+            break;
+        }
+        truncated.push(ch);
+        used += ch_width;
+    }
+    if width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+/// Whether to use colors: not when `NO_COLOR` is set, or the terminal says it's dumb.
+fn color_supported() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let dumb = env::var("TERM").is_ok_and(|term| term == "dumb");
+    !no_color && !dumb
+}
+
+/// A command that's only sent when colors are on.
+struct IfColor<C>(Option<C>);
+
+impl<C: Command> Command for IfColor<C> {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        self.0
+            .as_ref()
+            .map_or(Ok(()), |command| command.write_ansi(f))
+    }
+
+    #[cfg(windows)]
+    fn execute_winapi(&self) -> crossterm::Result<()> {
+        self.0
+            .as_ref()
+            .map_or(Ok(()), |command| command.execute_winapi())
+    }
+}
+
+fn if_color<C>(enabled: bool, command: C) -> IfColor<C> {
+    IfColor(enabled.then_some(command))
+}
+
+/// Without colors, turns reverse video on or off in place of a background that marks something.
+fn reverse_without_color(enabled: bool, marked: bool) -> IfColor<style::SetAttribute> {
+    let attribute = if marked {
+        style::Attribute::Reverse
+    } else {
+        style::Attribute::NoReverse
+    };
+    IfColor((!enabled).then_some(style::SetAttribute(attribute)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text rows, the message row and the status row for a terminal `height` rows tall.
+    fn layout(height: u16, zen: bool) -> (usize, u16, u16) {
+        let mut screen = Screen::with_size(80, height);
+        screen.zen = zen;
+        (
+            screen.text_rows(),
+            screen.message_row(),
+            screen.status_row(),
         )
     }
+
+    #[test]
+    fn too_short_for_text_at_one_and_two_rows() {
+        for height in [1, 2] {
+            let screen = Screen::with_size(80, height);
+            assert!(screen.is_too_small());
+            let (text_rows, message_row, _) = layout(height, false);
+            assert!(text_rows <= message_row as usize);
+        }
+        assert_eq!(layout(1, false), (0, 0, 0));
+        assert_eq!(layout(2, false), (0, 0, 1));
+    }
+
+    #[test]
+    fn messages_get_their_own_row_at_three_rows() {
+        assert!(!Screen::with_size(80, 3).is_too_small());
+        assert_eq!(layout(3, false), (1, 1, 2));
+        // Without the status bar, messages move down to the last row
+        assert_eq!(layout(3, true), (2, 2, 2));
+    }
+
+    /// A screen 10 columns wide with 3 rows of text, showing `text` with the cursor at `cursor`.
+    fn view(text: &str, cursor: usize, config: &Config) -> (Screen, Buffer) {
+        let mut screen = Screen::with_size(10, 5);
+        let mut buffer = Buffer::from(text);
+        buffer.set_cursor_pos(cursor);
+        screen.update_scroll_offset(&buffer, config);
+        (screen, buffer)
+    }
+
+    fn wrapping() -> Config {
+        Config {
+            wrap: true,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn wrapped_rows_leave_room_for_the_marker() {
+        let config = wrapping();
+        let (screen, buffer) = view("abcdefghijklmnopqrstuvwxyz", 12, &config);
+        assert_eq!(screen.wrap_starts(&buffer, 0, &config), vec![0, 10, 19]);
+        assert_eq!(screen.cursor_position(&buffer, &config), (3, 1));
+        let (screen, buffer) = view("abcdefghijklmnopqrstuvwxyz", 26, &config);
+        assert_eq!(screen.cursor_position(&buffer, &config), (8, 2));
+        let config = Config {
+            wrap_markers: false,
+            ..wrapping()
+        };
+        let (screen, buffer) = view("abcdefghijklmnopqrstuvwxyz", 12, &config);
+        assert_eq!(screen.wrap_starts(&buffer, 0, &config), vec![0, 10, 20]);
+        assert_eq!(screen.cursor_position(&buffer, &config), (2, 1));
+    }
+
+    #[test]
+    fn line_filling_its_last_row_gets_a_row_for_the_cursor() {
+        let config = wrapping();
+        let (screen, buffer) = view("0123456789", 10, &config);
+        assert_eq!(screen.wrap_starts(&buffer, 0, &config), vec![0, 10]);
+        assert_eq!(screen.cursor_position(&buffer, &config), (1, 1));
+    }
+
+    #[test]
+    fn wide_characters_wrap_whole() {
+        let config = wrapping();
+        let (screen, buffer) = view("abcdefghi字x", 10, &config);
+        assert_eq!(screen.wrap_starts(&buffer, 0, &config), vec![0, 9]);
+        assert_eq!(screen.cursor_position(&buffer, &config), (3, 1));
+    }
+
+    #[test]
+    fn scrolling_counts_wrapped_rows() {
+        let config = wrapping();
+        let text = format!("{}\nb\nc\n", "a".repeat(25));
+        // The first line takes all three rows, so the view has to start below it
+        let (screen, buffer) = view(&text, 26, &config);
+        assert_eq!(screen.scroll_offset(), 1);
+        assert_eq!(screen.cursor_position(&buffer, &config), (0, 0));
+        let (screen, buffer) = view(&text, 24, &config);
+        assert_eq!(screen.scroll_offset(), 0);
+        assert_eq!(screen.cursor_position(&buffer, &config), (6, 2));
+    }
+
+    #[test]
+    fn long_lines_scroll_sideways_to_the_cursor() {
+        let config = Config::default();
+        let text = "a".repeat(30);
+        let (mut screen, mut buffer) = view(&text, 20, &config);
+        // The last column is kept for the clip marker
+        assert_eq!(screen.col_offset, 12);
+        assert_eq!(screen.cursor_position(&buffer, &config), (8, 0));
+        buffer.set_cursor_pos(5);
+        screen.update_scroll_offset(&buffer, &config);
+        assert_eq!(screen.col_offset, 4);
+        assert_eq!(screen.cursor_position(&buffer, &config), (1, 0));
+        buffer.set_cursor_pos(0);
+        screen.update_scroll_offset(&buffer, &config);
+        assert_eq!(screen.col_offset, 0);
+        // Wrapped lines are never scrolled sideways
+        let (screen, _) = view(&text, 20, &wrapping());
+        assert_eq!(screen.col_offset, 0);
+    }
+
+    #[test]
+    fn status_line_fits_long_wide_file_names() {
+        let name = format!("{}😀.txt", "長いファイル名".repeat(8));
+        let mut buffer = Buffer::from("text");
+        buffer.set_file_path(std::env::temp_dir().join(name));
+        let config = Config::default();
+        for width in 1..60 {
+            let mut screen = Screen::with_size(width, 10);
+            let status = screen.status_line(&buffer, &config);
+            assert_eq!(display_width(&status), width as usize, "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn truncation_keeps_wide_characters_whole() {
+        assert_eq!(truncate_to_width("字字字", 6), "字字字");
+        assert_eq!(truncate_to_width("字字字", 5), "字字…");
+        assert_eq!(truncate_to_width("字字字", 4), "字…");
+        assert_eq!(truncate_to_width("😀😀", 2), "…");
+        assert_eq!(truncate_to_width("😀😀", 0), "");
+    }
+
+    #[test]
+    fn cursor_stays_on_its_character_across_toggles() {
+        let mut screen = Screen::with_size(20, 10);
+        let mut buffer = Buffer::from(format!("{}\nb\n", "a".repeat(30)).as_str());
+        buffer.set_cursor_pos(25);
+        let position = |screen: &mut Screen, config: &Config| {
+            screen.update_scroll_offset(&buffer, config);
+            let (x, y) = screen.cursor_position(&buffer, config);
+            assert!(x < screen.text_width(config));
+            (x, y)
+        };
+        let plain = Config::default();
+        let (x, _) = position(&mut screen, &plain);
+        assert_eq!(x, 25 - screen.col_offset);
+        // Wrapped, the cursor goes down to the second row of its line
+        assert_eq!(position(&mut screen, &wrapping()), (6, 1));
+        let minimap = Config {
+            minimap: true,
+            ..Config::default()
+        };
+        let (x, _) = position(&mut screen, &minimap);
+        assert_eq!(x, 25 - screen.col_offset);
+        let scrollbar = Config {
+            scrollbar: true,
+            ..Config::default()
+        };
+        let (x, _) = position(&mut screen, &scrollbar);
+        assert_eq!(x, 25 - screen.col_offset);
+        assert!(screen.toggle_zen());
+        assert_eq!(position(&mut screen, &plain).1, 0);
+        screen.toggle_zen();
+        // The view stays scrolled as far as the narrower text area needed
+        let (x, _) = position(&mut screen, &plain);
+        assert_eq!(x, 25 - screen.col_offset);
+        assert_eq!(buffer.cursor_pos(), 25);
+    }
+
+    #[test]
+    fn undo_and_redo_bring_the_change_into_view() {
+        let config = Config::default();
+        let text: String = (0..50).map(|idx| format!("line {}\n", idx)).collect();
+        let mut screen = Screen::with_size(20, 10);
+        let mut buffer = Buffer::from(text.as_str());
+        buffer.move_to(40, 4);
+        buffer.insert_str(" edited");
+        buffer.end_undo_step();
+        buffer.move_to(0, 0);
+        screen.update_scroll_offset(&buffer, &config);
+        assert_eq!(screen.scroll_offset(), 0);
+
+        buffer.undo();
+        assert_eq!(buffer.get_cursor_xy(), (4, 40));
+        screen.update_scroll_offset(&buffer, &config);
+        assert_eq!(screen.cursor_position(&buffer, &config), (4, 7));
+
+        buffer.move_to(0, 0);
+        screen.update_scroll_offset(&buffer, &config);
+        buffer.redo();
+        assert_eq!(buffer.get_cursor_xy(), (11, 40));
+        screen.update_scroll_offset(&buffer, &config);
+        assert_eq!(screen.cursor_position(&buffer, &config), (11, 7));
+    }
+
+    #[test]
+    fn status_bar_counts_the_selection() {
+        let config = Config::default();
+        let mut screen = Screen::with_size(80, 10);
+        let mut buffer = Buffer::from("one\ntwo\nthree\n");
+        assert!(!screen.format_status(&buffer, &config).contains("selected"));
+        buffer.start_selection();
+        buffer.set_cursor_pos(1);
+        assert!(screen
+            .format_status(&buffer, &config)
+            .ends_with(" - 1 line, 1 char selected"));
+        buffer.set_cursor_pos(10);
+        assert!(screen
+            .format_status(&buffer, &config)
+            .ends_with(" - 3 lines, 10 chars selected"));
+        buffer.clear_selection();
+        assert!(!screen.format_status(&buffer, &config).contains("selected"));
+    }
 }